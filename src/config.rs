@@ -1,17 +1,402 @@
-use crate::prelude::Result;
+use crate::{prelude::Result, models::orders::Order};
+use clap::Args;
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub base_url: String,
     pub api_key: String,
+    #[serde(skip)]
+    pub dry_run: bool,
+    /// Counts and errors of the current run
+    #[serde(skip)]
+    pub report: crate::report::RunReport,
+    #[serde(default = "default_dry_run_path")]
+    pub dry_run_path: String,
     pub pull_order_settings: PullOrderSettings,
+    #[serde(default)]
+    pub incident_settings: IncidentSettings,
+    #[serde(default)]
+    pub offer_settings: OfferSettings,
+    #[serde(default)]
+    pub product_settings: ProductSettings,
+    #[serde(default)]
+    pub transaction_settings: TransactionSettings,
+    #[serde(default)]
+    pub reconcile_settings: ReconcileSettings,
+    #[serde(default)]
+    pub return_settings: ReturnSettings,
+    #[serde(default)]
+    pub deadline_settings: DeadlineSettings,
+    #[serde(default)]
+    pub unknown_fields: UnknownFieldsMode,
+    #[serde(default)]
+    pub additional_field_settings: AdditionalFieldSettings,
+    #[serde(default)]
+    pub tracking_settings: TrackingSettings,
+    #[serde(default)]
+    pub log_settings: LogSettings,
+}
+
+/// What to do with the fields sent by the API that the models don't know about
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownFieldsMode {
+    /// Carry them into the exported files
+    #[default]
+    Keep,
+    /// Drop them before exporting
+    Strip,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PullOrderSettings {
     pub order_state_codes: Vec<String>,
     pub order_path: String,
+    #[serde(default)]
+    pub acceptance: AcceptanceSettings,
+    #[serde(default)]
+    pub filters: OrderFilters,
+    /// Replace unexpected nulls and missing fields in order payloads by defaults instead of failing
+    #[serde(default)]
+    pub tolerant: bool,
+    /// Number of orders of a page accepted and written at the same time
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// Upper bound on the acceptance calls started every second, unbounded when unset
+    #[serde(default)]
+    pub requests_per_second: Option<f64>,
+}
+
+/// Filters applied when pulling orders, either from the config or the command line.
+///
+/// The first group is sent to the orders endpoint, the second is applied locally on the fetched orders.
+#[derive(Debug, Default, Clone, Args, Deserialize)]
+#[serde(default)]
+pub struct OrderFilters {
+    /// Only pull orders from these channels
+    #[arg(long = "channel", value_delimiter = ',')]
+    pub channel_codes: Vec<String>,
+    /// Only pull orders created from this date (ISO 8601)
+    #[arg(long = "from", value_parser = parse_date)]
+    pub start_date: Option<String>,
+    /// Only pull orders created until this date (ISO 8601)
+    #[arg(long = "to", value_parser = parse_date)]
+    pub end_date: Option<String>,
+    /// Only pull orders updated from this date (ISO 8601)
+    #[arg(long = "updated-from", value_parser = parse_date)]
+    pub start_update_date: Option<String>,
+    /// Only pull orders updated until this date (ISO 8601)
+    #[arg(long = "updated-to", value_parser = parse_date)]
+    pub end_update_date: Option<String>,
+    /// Only pull orders that were (or were not) debited to the customer
+    #[arg(long)]
+    pub customer_debited: Option<bool>,
+    /// Only pull orders with these customer references
+    #[arg(long = "customer-reference", value_delimiter = ',')]
+    pub order_references_for_customer: Vec<String>,
+    /// Only pull orders with these seller references
+    #[arg(long = "seller-reference", value_delimiter = ',')]
+    pub order_references_for_seller: Vec<String>,
+    /// Sort the orders by dateCreated or dateUpdated
+    #[arg(long)]
+    pub sort: Option<String>,
+    /// Only keep orders shipped to these zones
+    #[arg(long = "shipping-zone", value_delimiter = ',')]
+    pub shipping_zone_codes: Vec<String>,
+    /// Only keep orders with these shipping types
+    #[arg(long = "shipping-type", value_delimiter = ',')]
+    pub shipping_type_codes: Vec<String>,
+    /// Only keep orders fulfilled by these centers
+    #[arg(long = "fulfillment-center", value_delimiter = ',')]
+    pub fulfillment_center_codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AcceptanceMode {
+    /// Accept every order waiting for acceptance
+    Accept,
+    /// Hold orders for manual review until the acceptance deadline is near
+    Review,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AcceptanceDecision {
+    Accept,
+    Refuse,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AcceptanceSettings {
+    pub mode: AcceptanceMode,
+    /// Decision taken for orders still under review when the deadline is near
+    pub default_decision: AcceptanceDecision,
+    /// Delay after the order creation after which the marketplace refuses the order
+    pub acceptance_delay_hours: i64,
+    /// How long before the acceptance deadline the default decision is taken
+    pub safety_margin_hours: i64,
+    pub review_path: String,
+}
+
+impl Default for AcceptanceSettings {
+    fn default() -> Self {
+        Self {
+            mode: AcceptanceMode::Accept,
+            default_decision: AcceptanceDecision::Accept,
+            acceptance_delay_hours: 24,
+            safety_margin_hours: 2,
+            review_path: "review".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IncidentSettings {
+    pub incident_path: String,
+}
+
+impl Default for IncidentSettings {
+    fn default() -> Self {
+        Self {
+            incident_path: "incidents".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct OfferSettings {
+    pub import_mode: String,
+    pub product_id_type: String,
+    pub default_state_code: String,
+    pub poll_interval: u64,
+    /// Number of status checks after which an import that is still running is given up on
+    pub max_polls: u32,
+    pub report_path: String,
+}
+
+impl Default for OfferSettings {
+    fn default() -> Self {
+        Self {
+            import_mode: "NORMAL".to_string(),
+            product_id_type: "SHOP_SKU".to_string(),
+            default_state_code: "11".to_string(),
+            poll_interval: 30,
+            max_polls: default_max_polls(),
+            report_path: "reports".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ProductSettings {
+    pub poll_interval: u64,
+    /// Number of status checks after which an import that is still running is given up on
+    pub max_polls: u32,
+    pub report_path: String,
+}
+
+impl Default for ProductSettings {
+    fn default() -> Self {
+        Self {
+            poll_interval: 30,
+            max_polls: default_max_polls(),
+            report_path: "reports".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TransactionSettings {
+    pub transaction_path: String,
+    pub page_size: u32,
+}
+
+impl Default for TransactionSettings {
+    fn default() -> Self {
+        Self {
+            transaction_path: "transactions".to_string(),
+            page_size: 100,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ReconcileSettings {
+    pub report_path: String,
+    pub tolerance: f64,
+    /// States of the orders the ERP is expected to have invoiced
+    pub order_state_codes: Vec<String>,
+}
+
+impl Default for ReconcileSettings {
+    fn default() -> Self {
+        Self {
+            report_path: "reports".to_string(),
+            tolerance: 0.01,
+            order_state_codes: ["SHIPPED", "TO_COLLECT", "RECEIVED", "CLOSED"].map(str::to_string).to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ReturnSettings {
+    pub return_state_codes: Vec<String>,
+    pub return_path: String,
+}
+
+impl Default for ReturnSettings {
+    fn default() -> Self {
+        Self {
+            return_state_codes: vec![],
+            return_path: "returns".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct DeadlineSettings {
+    pub window_hours: i64,
+    pub webhook_url: Option<String>,
+    pub mail_path: Option<String>,
+    pub mail_from: String,
+    pub mail_to: Vec<String>,
+}
+
+impl Default for DeadlineSettings {
+    fn default() -> Self {
+        Self {
+            window_hours: 24,
+            webhook_url: None,
+            mail_path: None,
+            mail_from: "wunder@localhost".to_string(),
+            mail_to: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AdditionalFieldSettings {
+    /// File the marketplace's additional field definitions are cached in
+    pub cache_file: String,
+    /// How long the cached definitions are used before being fetched again
+    pub cache_ttl_hours: u64,
+    /// Check the additional fields of pulled orders against the definitions, not only against their types
+    pub validate_orders: bool,
+}
+
+impl Default for AdditionalFieldSettings {
+    fn default() -> Self {
+        Self {
+            cache_file: "additional_fields.json".to_string(),
+            cache_ttl_hours: 24,
+            validate_orders: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TrackingSettings {
+    /// Number of orders whose tracking info is pushed at the same time
+    pub concurrency: usize,
+    /// Folder the result files of batch pushes are written to
+    pub report_path: String,
+}
+
+impl Default for TrackingSettings {
+    fn default() -> Self {
+        Self {
+            concurrency: default_concurrency(),
+            report_path: "reports".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LogSettings {
+    /// File the log is appended to, no log file when empty
+    pub path: String,
+    /// Level, or `tracing` filter directives, of the log file and of the console. `RUST_LOG` overrides it.
+    pub level: String,
+    /// Format of the log file, the console is always text
+    pub format: LogFormat,
+    pub rotation: LogRotation,
+    /// Size from which the log file is rotated, with the size rotation
+    pub max_size_mb: u64,
+    /// Number of rotated log files kept, older ones are deleted
+    pub max_files: usize,
+    /// Log the requests sent to the marketplace and its responses, without credentials and customer details
+    pub trace_http: bool,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            path: "wunder.log".to_string(),
+            level: "error".to_string(),
+            format: LogFormat::Text,
+            rotation: LogRotation::Never,
+            max_size_mb: 10,
+            max_files: 7,
+            trace_http: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, for log shippers
+    Json,
+}
+
+/// When the log file is moved aside and a new one started
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    #[default]
+    Never,
+    /// On the first line written on a new day
+    Daily,
+    /// When the file would grow past `max_size_mb`
+    Size,
+}
+
+fn default_dry_run_path() -> String {
+    "dry-run".to_string()
+}
+
+fn default_concurrency() -> usize {
+    4
+}
+
+/// An hour of status checks at the default poll interval
+fn default_max_polls() -> u32 {
+    120
+}
+
+/// Checks that a date given on the command line is an ISO 8601 date (`2022-12-01`) or date and time
+/// (`2022-12-01T10:00:00Z`), before the API rejects it.
+pub fn parse_date(value: &str) -> std::result::Result<String, String> {
+    let is_date_time = chrono::DateTime::parse_from_rfc3339(value).is_ok();
+    if is_date_time || chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok() {
+        Ok(value.to_string())
+    } else {
+        Err("expected an ISO 8601 date like 2022-12-01 or 2022-12-01T10:00:00Z".to_string())
+    }
 }
 
 impl Config {
@@ -22,10 +407,165 @@ impl Config {
             .try_deserialize()
             .map_err(Into::into)
     }
+
+    /// Switches to dry-run mode, moving every output folder under the dry-run path.
+    pub fn enable_dry_run(&mut self) {
+        let scratch = std::path::PathBuf::from(&self.dry_run_path);
+        let relocate = |path: &mut String| {
+            let relative = path.trim_start_matches(std::path::MAIN_SEPARATOR).to_string();
+            *path = scratch.join(relative).display().to_string();
+        };
+
+        self.dry_run = true;
+        relocate(&mut self.pull_order_settings.order_path);
+        relocate(&mut self.pull_order_settings.acceptance.review_path);
+        relocate(&mut self.incident_settings.incident_path);
+        relocate(&mut self.offer_settings.report_path);
+        relocate(&mut self.product_settings.report_path);
+        relocate(&mut self.transaction_settings.transaction_path);
+        relocate(&mut self.reconcile_settings.report_path);
+        relocate(&mut self.return_settings.return_path);
+        relocate(&mut self.tracking_settings.report_path);
+        relocate(&mut self.additional_field_settings.cache_file);
+        if let Some(mail_path) = self.deadline_settings.mail_path.as_mut() {
+            relocate(mail_path);
+        }
+    }
+}
+
+impl ReturnSettings {
+    pub fn return_state_codes(&self) -> String {
+        self.return_state_codes.join(",")
+    }
+}
+
+impl OrderFilters {
+    /// Overrides these filters with the ones set in `other`.
+    pub fn merge(&self, other: &OrderFilters) -> OrderFilters {
+        fn pick<T: Clone>(a: &Option<T>, b: &Option<T>) -> Option<T> {
+            b.clone().or_else(|| a.clone())
+        }
+        fn pick_all(a: &[String], b: &[String]) -> Vec<String> {
+            if b.is_empty() { a.to_vec() } else { b.to_vec() }
+        }
+
+        OrderFilters {
+            channel_codes: pick_all(&self.channel_codes, &other.channel_codes),
+            start_date: pick(&self.start_date, &other.start_date),
+            end_date: pick(&self.end_date, &other.end_date),
+            start_update_date: pick(&self.start_update_date, &other.start_update_date),
+            end_update_date: pick(&self.end_update_date, &other.end_update_date),
+            customer_debited: pick(&self.customer_debited, &other.customer_debited),
+            order_references_for_customer: pick_all(&self.order_references_for_customer, &other.order_references_for_customer),
+            order_references_for_seller: pick_all(&self.order_references_for_seller, &other.order_references_for_seller),
+            sort: pick(&self.sort, &other.sort),
+            shipping_zone_codes: pick_all(&self.shipping_zone_codes, &other.shipping_zone_codes),
+            shipping_type_codes: pick_all(&self.shipping_type_codes, &other.shipping_type_codes),
+            fulfillment_center_codes: pick_all(&self.fulfillment_center_codes, &other.fulfillment_center_codes),
+        }
+    }
+
+    /// The filters supported by the orders endpoint, as query parameters.
+    pub fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![];
+        if !self.channel_codes.is_empty() {
+            query.push(("channel_codes", self.channel_codes.join(",")));
+        }
+        if let Some(date) = &self.start_date {
+            query.push(("start_date", date.clone()));
+        }
+        if let Some(date) = &self.end_date {
+            query.push(("end_date", date.clone()));
+        }
+        if let Some(date) = &self.start_update_date {
+            query.push(("start_update_date", date.clone()));
+        }
+        if let Some(date) = &self.end_update_date {
+            query.push(("end_update_date", date.clone()));
+        }
+        if let Some(debited) = self.customer_debited {
+            query.push(("customer_debited", debited.to_string()));
+        }
+        if !self.order_references_for_customer.is_empty() {
+            query.push(("order_references_for_customer", self.order_references_for_customer.join(",")));
+        }
+        if !self.order_references_for_seller.is_empty() {
+            query.push(("order_references_for_seller", self.order_references_for_seller.join(",")));
+        }
+        if let Some(sort) = &self.sort {
+            query.push(("sort", sort.clone()));
+        }
+        query
+    }
+
+    /// Whether `order` passes the filters the orders endpoint does not support.
+    pub fn matches(&self, order: &Order) -> bool {
+        (self.shipping_zone_codes.is_empty() || self.shipping_zone_codes.contains(&order.shipping_zone_code))
+            && (self.shipping_type_codes.is_empty() || self.shipping_type_codes.contains(&order.shipping_type_code))
+            && (self.fulfillment_center_codes.is_empty() || self.fulfillment_center_codes.contains(&order.fulfillment.center.code))
+    }
 }
 
 impl PullOrderSettings {
     pub fn order_state_codes(&self) -> String {
         self.order_state_codes.join(",")
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Debug, Parser)]
+    struct Cli {
+        #[command(flatten)]
+        filters: OrderFilters,
+    }
+
+    fn cli(args: &[&str]) -> std::result::Result<OrderFilters, clap::Error> {
+        Cli::try_parse_from(std::iter::once("pull-orders").chain(args.iter().copied())).map(|cli| cli.filters)
+    }
+
+    #[test]
+    fn command_line_filters_override_the_config() {
+        let configured = OrderFilters {
+            channel_codes: vec!["FR".to_string()],
+            start_date: Some("2022-11-01".to_string()),
+            sort: Some("dateCreated".to_string()),
+            shipping_zone_codes: vec!["FR".to_string(), "BE".to_string()],
+            ..OrderFilters::default()
+        };
+        let given = cli(&["--channel", "DE,AT", "--to", "2022-12-01T10:00:00Z", "--customer-debited", "true"]).unwrap();
+
+        let merged = configured.merge(&given);
+        assert_eq!(merged.channel_codes, vec!["DE", "AT"]);
+        assert_eq!(merged.start_date.as_deref(), Some("2022-11-01"));
+        assert_eq!(merged.end_date.as_deref(), Some("2022-12-01T10:00:00Z"));
+        assert_eq!(merged.customer_debited, Some(true));
+        assert_eq!(merged.sort.as_deref(), Some("dateCreated"));
+        assert_eq!(merged.shipping_zone_codes, vec!["FR", "BE"]);
+        assert!(merged.query().contains(&("channel_codes", "DE,AT".to_string())));
+    }
+
+    #[test]
+    fn dates_are_checked_on_the_command_line() {
+        assert!(cli(&["--from", "2022-12-01", "--updated-to", "2022-12-01T10:00:00+01:00"]).is_ok());
+        for date in ["01/12/2022", "2022-12-01 10:00", "yesterday"] {
+            let error = cli(&["--from", date]).unwrap_err();
+            assert_eq!(error.kind(), clap::error::ErrorKind::ValueValidation, "{}", date);
+        }
+    }
+
+    #[test]
+    fn local_filters_match_zone_type_and_center() {
+        let order = crate::models::orders::fixture_order("shipping-b2b");
+
+        assert!(OrderFilters::default().matches(&order));
+        assert!(cli(&["--shipping-zone", "BE,FR", "--shipping-type", "STD"]).unwrap().matches(&order));
+        assert!(cli(&["--fulfillment-center", "DEFAULT"]).unwrap().matches(&order));
+        assert!(!cli(&["--shipping-zone", "BE"]).unwrap().matches(&order));
+        assert!(!cli(&["--shipping-zone", "FR", "--shipping-type", "EXP"]).unwrap().matches(&order));
+        assert!(!cli(&["--fulfillment-center", "WAREHOUSE-2"]).unwrap().matches(&order));
+    }
+}
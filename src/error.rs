@@ -1,6 +1,6 @@
-use miette::Diagnostic;
+use miette::{Diagnostic, NamedSource, SourceSpan};
 
-use crate::models::invoices::OrderDocumentResponse;
+use crate::models::{api_errors::{ApiErrorResponse, FieldError}, invoices::OrderDocumentResponse};
 
 #[derive(thiserror::Error, Diagnostic, Debug)]
 pub enum WunderError {
@@ -8,10 +8,30 @@ pub enum WunderError {
     #[diagnostic(code(wunder::error::tracking_file_not_found))]
     TrackingFileNotFound(String),
 
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    InvalidTrackingFile(Box<TrackingFileError>),
+
+    #[error("{0}")]
+    #[diagnostic(code(wunder::error::invalid_tracking_record))]
+    InvalidTrackingRecord(String),
+
+    #[error("Could not push tracking info for {failed} of {total} orders")]
+    #[diagnostic(code(wunder::error::tracking_push_failed), help("See the results at {result_file}"))]
+    TrackingPushFailed {
+        failed: usize,
+        total: usize,
+        result_file: String,
+    },
+
     #[error("Invoice file does not exist: {0}")]
     #[diagnostic(code(wunder::error::invoice_file_not_found))]
     InvoiceFileNotFound(String),
 
+    #[error("Not the path of a file with a UTF-8 name: {0}")]
+    #[diagnostic(code(wunder::error::invalid_file_name))]
+    InvalidFileName(String),
+
     #[error("Document format not supported: {0}")]
     #[diagnostic(code(wunder::error::document_format_not_supported))]
     DocumentFormatNotSupported(String),
@@ -20,6 +40,97 @@ pub enum WunderError {
     #[diagnostic(code(wunder::error::document_upload_error))]
     DocumentUploadError(OrderDocumentResponse),
 
+    #[error("Unknown reason code {0} for reason type {1}")]
+    #[diagnostic(code(wunder::error::unknown_reason_code))]
+    UnknownReasonCode(String, String),
+
+    #[error("Stock file does not exist: {0}")]
+    #[diagnostic(code(wunder::error::stock_file_not_found))]
+    StockFileNotFound(String),
+
+    #[error("Stock format not supported: {0}")]
+    #[diagnostic(code(wunder::error::stock_format_not_supported), help("Stock files must be .csv or .xml"))]
+    StockFormatNotSupported(String),
+
+    #[error("Product file does not exist: {0}")]
+    #[diagnostic(code(wunder::error::product_file_not_found))]
+    ProductFileNotFound(String),
+
+    #[error("Offer import {import_id} rejected {rejected} of {read} lines")]
+    #[diagnostic(code(wunder::error::offers_rejected), help("The error report was written to {report_file}"))]
+    OffersRejected {
+        import_id: u64,
+        read: u32,
+        rejected: u32,
+        report_file: String,
+    },
+
+    #[error("Product import {import_id} rejected {rejected} of {read} lines")]
+    #[diagnostic(code(wunder::error::products_rejected), help("The error reports were written to {report_path}"))]
+    ProductsRejected {
+        import_id: u64,
+        read: u32,
+        rejected: u32,
+        report_path: String,
+    },
+
+    #[error("ERP export does not exist: {0}")]
+    #[diagnostic(code(wunder::error::erp_file_not_found))]
+    ErpFileNotFound(String),
+
+    #[error("Found {count} discrepancies between the ERP and the marketplace")]
+    #[diagnostic(code(wunder::error::reconciliation_discrepancies), help("See the report at {report_file}"))]
+    ReconciliationDiscrepancies {
+        count: usize,
+        report_file: String,
+    },
+
+    #[error("Return updates file does not exist: {0}")]
+    #[diagnostic(code(wunder::error::return_file_not_found))]
+    ReturnFileNotFound(String),
+
+    #[error("{overdue} orders missed their shipping deadline, {due_soon} are due soon")]
+    #[diagnostic(code(wunder::error::shipping_deadlines_at_risk))]
+    ShippingDeadlinesAtRisk {
+        overdue: usize,
+        due_soon: usize,
+    },
+
+    #[error("Orders not found: {0}")]
+    #[diagnostic(code(wunder::error::orders_not_found))]
+    OrdersNotFound(String),
+
+    #[error("Order {0} is not waiting for acceptance")]
+    #[diagnostic(code(wunder::error::order_not_waiting_acceptance))]
+    OrderNotWaitingAcceptance(String),
+
+    #[error("Import {0} failed: {1}")]
+    #[diagnostic(code(wunder::error::import_failed))]
+    ImportFailed(u64, String),
+
+    #[error("Import {import_id} is still {status} after {polls} status checks")]
+    #[diagnostic(code(wunder::error::import_timed_out), help("Check the import on the marketplace before pushing the file again, or raise max_polls"))]
+    ImportTimedOut {
+        import_id: u64,
+        polls: u32,
+        status: String,
+    },
+
+    #[error("Additional fields file does not exist: {0}")]
+    #[diagnostic(code(wunder::error::additional_field_file_not_found))]
+    AdditionalFieldFileNotFound(String),
+
+    #[error("{count} additional fields have invalid values, nothing was updated")]
+    #[diagnostic(code(wunder::error::invalid_additional_fields), help("{details}"))]
+    InvalidAdditionalFields {
+        count: usize,
+        details: String,
+    },
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Api(Box<ApiError>),
+
     #[error(transparent)]
     #[diagnostic(code(wunder::error::io))]
     Io(#[from] std::io::Error),
@@ -39,4 +150,187 @@ pub enum WunderError {
     #[error(transparent)]
     #[diagnostic(code(wunder::error::serde_json))]
     SerdeJson(#[from] serde_json::Error),
-}
\ No newline at end of file
+
+    #[error(transparent)]
+    #[diagnostic(code(wunder::error::csv))]
+    Csv(#[from] csv::Error),
+
+    #[error(transparent)]
+    #[diagnostic(code(wunder::error::hyper))]
+    Hyper(#[from] hyper::Error),
+}
+
+/// Exit status of the process, one per category of error so schedulers can decide whether to retry.
+///
+/// 2 is left to clap, which exits with it on invalid arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ExitStatus {
+    /// Any other error, e.g. an output file that can't be written
+    Failure = 1,
+    /// The config file is missing or invalid
+    Config = 3,
+    /// An input file given on the command line does not exist
+    FileNotFound = 4,
+    /// An input file, or a response, has invalid content
+    Invalid = 5,
+    /// The marketplace rejected a request (HTTP 4xx), retrying won't help
+    Rejected = 6,
+    /// The marketplace failed (HTTP 5xx) or could not be reached, retrying may help
+    Unavailable = 7,
+    /// Part of a batch could not be processed, see the result or report file
+    PartialFailure = 8,
+    /// The command ran, but found something that needs attention (discrepancies, deadlines at risk)
+    Attention = 9,
+    /// An import is still running on the marketplace, pushing the file again may import it twice
+    Pending = 10,
+}
+
+impl From<ExitStatus> for std::process::ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        Self::from(status as u8)
+    }
+}
+
+impl WunderError {
+    /// The exit status of the process when a command fails with this error
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            Self::Config(_) => ExitStatus::Config,
+            Self::TrackingFileNotFound(_)
+            | Self::InvoiceFileNotFound(_)
+            | Self::StockFileNotFound(_)
+            | Self::ProductFileNotFound(_)
+            | Self::ErpFileNotFound(_)
+            | Self::ReturnFileNotFound(_)
+            | Self::AdditionalFieldFileNotFound(_) => ExitStatus::FileNotFound,
+            Self::Io(e) if e.kind() == std::io::ErrorKind::NotFound => ExitStatus::FileNotFound,
+            Self::InvalidTrackingFile(_)
+            | Self::InvalidTrackingRecord(_)
+            | Self::InvalidFileName(_)
+            | Self::DocumentFormatNotSupported(_)
+            | Self::UnknownReasonCode(..)
+            | Self::StockFormatNotSupported(_)
+            | Self::OrdersNotFound(_)
+            | Self::OrderNotWaitingAcceptance(_)
+            | Self::InvalidAdditionalFields { .. }
+            | Self::QuickXml(_)
+            | Self::SerdeJson(_)
+            | Self::Csv(_) => ExitStatus::Invalid,
+            Self::DocumentUploadError(_) => ExitStatus::Rejected,
+            Self::Api(e) if e.status < 500 => ExitStatus::Rejected,
+            Self::Api(_) => ExitStatus::Unavailable,
+            Self::Reqwest(e) => match e.status() {
+                Some(status) if status.is_client_error() => ExitStatus::Rejected,
+                Some(_) => ExitStatus::Unavailable,
+                None if e.is_decode() => ExitStatus::Invalid,
+                None if e.is_builder() => ExitStatus::Failure,
+                None => ExitStatus::Unavailable,
+            },
+            Self::Hyper(_) => ExitStatus::Unavailable,
+            Self::ImportFailed(..) => ExitStatus::Rejected,
+            Self::ImportTimedOut { .. } => ExitStatus::Pending,
+            Self::TrackingPushFailed { .. } | Self::OffersRejected { .. } | Self::ProductsRejected { .. } => ExitStatus::PartialFailure,
+            Self::ReconciliationDiscrepancies { .. } | Self::ShippingDeadlinesAtRisk { .. } => ExitStatus::Attention,
+            Self::Io(_) => ExitStatus::Failure,
+        }
+    }
+}
+
+/// Error in a tracking file, pointing at the offending element
+#[derive(thiserror::Error, Diagnostic, Debug)]
+#[error("Invalid tracking file {file_name}")]
+#[diagnostic(code(wunder::error::invalid_tracking_file))]
+pub struct TrackingFileError {
+    pub file_name: String,
+    #[source_code]
+    pub source_code: NamedSource,
+    #[label("{label}")]
+    pub span: SourceSpan,
+    pub label: String,
+    #[help]
+    pub help: Option<String>,
+}
+
+/// Error response of the API, with what it said went wrong
+#[derive(thiserror::Error, Diagnostic, Debug)]
+#[error("{url} answered {status}: {message}")]
+#[diagnostic(code(wunder::error::api))]
+pub struct ApiError {
+    pub status: u16,
+    pub url: String,
+    pub message: String,
+    /// Fields of the request that were rejected
+    pub errors: Vec<FieldError>,
+    #[help]
+    pub help: Option<String>,
+}
+
+impl ApiError {
+    /// Builds the error from the `body` of a response, which may not be JSON
+    pub fn new(status: reqwest::StatusCode, url: String, body: &str) -> Self {
+        let response = serde_json::from_str::<ApiErrorResponse>(body).unwrap_or_default();
+        let message = match response.message {
+            Some(message) => message,
+            None if !body.trim().is_empty() && response.errors.is_empty() => body.trim().chars().take(200).collect(),
+            None => status.canonical_reason().unwrap_or("error").to_string(),
+        };
+
+        let mut help = response.errors.iter().map(|error| format!("- {}", error)).collect::<Vec<_>>();
+        let hint = match status.as_u16() {
+            400 if response.errors.is_empty() => Some("The marketplace rejected the content of the request"),
+            401 => Some("Check the api_key in config.toml"),
+            403 => Some("The API key is not allowed to do this, check the permissions of the shop"),
+            404 => Some("Check the ids given on the command line, and the base_url in config.toml"),
+            429 => Some("Too many requests, lower pull_order_settings.concurrency or set pull_order_settings.requests_per_second"),
+            500..=599 => Some("The marketplace failed, try again later"),
+            _ => None,
+        };
+        help.extend(hint.map(str::to_string));
+
+        Self {
+            status: status.as_u16(),
+            url,
+            message,
+            errors: response.errors,
+            help: if help.is_empty() { None } else { Some(help.join("\n")) },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_exit_with_their_category() {
+        assert_eq!(WunderError::TrackingFileNotFound("tracking.xml".to_string()).exit_status(), ExitStatus::FileNotFound);
+        assert_eq!(WunderError::Io(std::io::ErrorKind::NotFound.into()).exit_status(), ExitStatus::FileNotFound);
+        assert_eq!(WunderError::Io(std::io::ErrorKind::PermissionDenied.into()).exit_status(), ExitStatus::Failure);
+        assert_eq!(WunderError::Config(config::ConfigError::NotFound("base_url".to_string())).exit_status(), ExitStatus::Config);
+        assert_eq!(WunderError::InvalidTrackingRecord("carrier FEDEX is not registered".to_string()).exit_status(), ExitStatus::Invalid);
+        let failed = WunderError::TrackingPushFailed { failed: 1, total: 2, result_file: "reports/tracking.csv".to_string() };
+        assert_eq!(failed.exit_status(), ExitStatus::PartialFailure);
+        assert_eq!(WunderError::ShippingDeadlinesAtRisk { overdue: 1, due_soon: 0 }.exit_status(), ExitStatus::Attention);
+        assert_eq!(WunderError::ImportFailed(7, "invalid file".to_string()).exit_status(), ExitStatus::Rejected);
+        assert_eq!(WunderError::ImportTimedOut { import_id: 7, polls: 10, status: "RUNNING".to_string() }.exit_status(), ExitStatus::Pending);
+        assert_eq!(std::process::ExitCode::from(ExitStatus::Unavailable), std::process::ExitCode::from(7));
+    }
+
+    #[test]
+    fn api_errors_keep_the_body() {
+        let body = r#"{"status": 400, "message": "Invalid tracking", "errors": [{"field": "tracking_number", "code": "REQUIRED", "message": "must not be empty"}]}"#;
+        let error = ApiError::new(reqwest::StatusCode::BAD_REQUEST, "http://localhost/api/orders/1/tracking".to_string(), body);
+        assert_eq!(error.to_string(), "http://localhost/api/orders/1/tracking answered 400: Invalid tracking");
+        assert_eq!(error.help.as_deref(), Some("- tracking_number: must not be empty (REQUIRED)"));
+        assert_eq!(WunderError::Api(Box::new(error)).exit_status(), ExitStatus::Rejected);
+
+        let error = ApiError::new(reqwest::StatusCode::UNAUTHORIZED, "http://localhost/api/orders".to_string(), "");
+        assert_eq!(error.message, "Unauthorized");
+        assert_eq!(error.help.as_deref(), Some("Check the api_key in config.toml"));
+
+        let error = ApiError::new(reqwest::StatusCode::BAD_GATEWAY, "http://localhost/api/orders".to_string(), "<html>Bad gateway</html>");
+        assert_eq!(error.message, "<html>Bad gateway</html>");
+        assert_eq!(WunderError::Api(Box::new(error)).exit_status(), ExitStatus::Unavailable);
+    }
+}
@@ -0,0 +1,224 @@
+//! Opt-in tracing of the HTTP exchanges with the marketplace.
+//!
+//! Requests and responses are logged under the `wunder::http` target, in a span per exchange carrying the
+//! method and URL, with the status and latency logged along the response. Credentials and customer details are
+//! redacted from what is logged.
+
+use std::time::Instant;
+
+use futures_util::{future::BoxFuture, FutureExt};
+use reqwest::{header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE}, RequestBuilder, Response, ResponseBuilderExt};
+use serde_json::Value;
+use tracing::{debug, Instrument};
+
+use crate::config::Config;
+
+/// Target of the HTTP traces, enabled by `log_settings.trace_http` or `--trace-http`
+pub const TARGET: &str = "wunder::http";
+
+/// Placeholder for values that must not end up in logs
+const REDACTED: &str = "<redacted>";
+
+/// Headers carrying credentials
+const SECRET_HEADERS: [&str; 4] = ["authorization", "proxy-authorization", "cookie", "set-cookie"];
+
+/// Largest response body read to be logged, bigger ones are left to the caller
+const MAX_LOGGED_BODY: u64 = 1024 * 1024;
+
+/// Fields of customers, contacts and addresses identifying a person
+const PERSONAL_FIELDS: [&str; 10] = [
+    "firstname",
+    "lastname",
+    "company",
+    "street_1",
+    "street_2",
+    "zip_code",
+    "city",
+    "phone",
+    "phone_secondary",
+    "email",
+];
+
+pub trait SendTraced {
+    /// Sends the request, tracing it and its response when HTTP tracing is on
+    fn send_traced(self, config: &Config) -> BoxFuture<'static, reqwest::Result<Response>>;
+}
+
+impl SendTraced for RequestBuilder {
+    fn send_traced(self, config: &Config) -> BoxFuture<'static, reqwest::Result<Response>> {
+        if !config.log_settings.trace_http {
+            return self.send().boxed();
+        }
+
+        async move {
+            // streamed bodies (uploads) can't be cloned, their request is only known by its response
+            let (span, request) = match self.try_clone().map(RequestBuilder::build) {
+                Some(Ok(request)) => {
+                    let span = tracing::debug_span!(target: TARGET, "http", method = %request.method(), url = %request.url());
+                    (span, Some(request))
+                },
+                _ => (tracing::debug_span!(target: TARGET, "http"), None),
+            };
+            match request {
+                Some(request) => {
+                    let body = match request.body() {
+                        Some(body) => body.as_bytes().map_or_else(|| "<streamed body>".to_string(), redact_body),
+                        None => String::new(),
+                    };
+                    debug!(target: TARGET, parent: &span, headers = ?redact_headers(request.headers()), %body, "Request");
+                },
+                None => debug!(target: TARGET, parent: &span, "Request with a streamed body"),
+            }
+
+            let start = Instant::now();
+            let response = match self.send().instrument(span.clone()).await {
+                Ok(response) => response,
+                Err(e) => {
+                    debug!(target: TARGET, parent: &span, latency_ms = start.elapsed().as_millis() as u64, error = %e, "Request failed");
+                    return Err(e);
+                },
+            };
+            let latency_ms = start.elapsed().as_millis() as u64;
+
+            // documents and other downloads are streamed to the caller, only their size is logged
+            if !is_logged(response.headers(), response.content_length()) {
+                debug!(
+                    target: TARGET,
+                    parent: &span,
+                    url = %response.url(),
+                    status = response.status().as_u16(),
+                    latency_ms,
+                    headers = ?redact_headers(response.headers()),
+                    body = %response.content_length().map_or_else(|| "<streamed body>".to_string(), |length| format!("<{} bytes>", length)),
+                    "Response"
+                );
+                return Ok(response);
+            }
+
+            // the body is read to be logged, the response is rebuilt around it
+            let status = response.status();
+            let version = response.version();
+            let url = response.url().clone();
+            let headers = response.headers().clone();
+            let body = response.bytes().await?;
+            debug!(
+                target: TARGET,
+                parent: &span,
+                url = %url,
+                status = status.as_u16(),
+                latency_ms,
+                headers = ?redact_headers(&headers),
+                body = %redact_body(&body),
+                "Response"
+            );
+
+            let mut builder = hyper::http::Response::builder().status(status).version(version).url(url);
+            if let Some(response_headers) = builder.headers_mut() {
+                *response_headers = headers;
+            }
+            let response = builder.body(body).expect("status and headers come from a valid response");
+            Ok(Response::from(response))
+        }.boxed()
+    }
+}
+
+/// The value of a header as it can be shown, credentials are redacted
+pub fn header_value<'a>(name: &HeaderName, value: &'a HeaderValue) -> &'a str {
+    if SECRET_HEADERS.contains(&name.as_str()) {
+        REDACTED
+    } else {
+        value.to_str().unwrap_or("<binary>")
+    }
+}
+
+fn redact_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers.iter().map(|(name, value)| (name.to_string(), header_value(name, value).to_string())).collect()
+}
+
+/// Whether a response body is read to be logged: JSON bodies that are not too big
+fn is_logged(headers: &HeaderMap, content_length: Option<u64>) -> bool {
+    let json = headers.get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.contains("json"));
+    json && content_length.unwrap_or_default() <= MAX_LOGGED_BODY
+}
+
+/// JSON bodies are logged without personal fields, other bodies only by their size
+pub(crate) fn redact_body(body: &[u8]) -> String {
+    if body.is_empty() {
+        return String::new();
+    }
+    match serde_json::from_slice::<Value>(body) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value.to_string()
+        },
+        Err(_) => format!("<{} bytes>", body.len()),
+    }
+}
+
+fn redact_value(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let personal = PERSONAL_FIELDS.contains(&key.as_str()) || key.ends_with("_email");
+                if personal && !value.is_null() {
+                    *value = Value::from(REDACTED);
+                } else {
+                    redact_value(value);
+                }
+            }
+        },
+        Value::Array(items) => items.iter_mut().for_each(redact_value),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn customer_details_are_redacted() {
+        let body = serde_json::to_vec(&crate::models::orders::fixture_order("waiting-acceptance")).unwrap();
+        let redacted: Value = serde_json::from_str(&redact_body(&body)).unwrap();
+
+        let customer = &redacted["customer"];
+        assert_eq!(customer["firstname"], REDACTED);
+        assert_eq!(customer["lastname"], REDACTED);
+        assert_eq!(customer["customer_id"], "CUST-1");
+        for address in ["billing_address", "shipping_address"] {
+            assert_eq!(customer[address]["street_1"], REDACTED);
+            assert_eq!(customer[address]["phone"], REDACTED);
+            assert_eq!(customer[address]["country_iso_code"], "FRA");
+        }
+        assert_eq!(redacted["customer_notification_email"], REDACTED);
+        assert_eq!(redacted["order_id"], "1001-A");
+
+        assert_eq!(redact_body(b"<Order>Jeanne</Order>"), "<21 bytes>");
+        assert_eq!(redact_body(b""), "");
+    }
+
+    #[test]
+    fn only_small_json_bodies_are_logged() {
+        let mut headers = HeaderMap::new();
+        assert!(!is_logged(&headers, Some(10)));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/pdf"));
+        assert!(!is_logged(&headers, Some(10)));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json;charset=UTF-8"));
+        assert!(is_logged(&headers, Some(10)));
+        assert!(is_logged(&headers, None));
+        assert!(!is_logged(&headers, Some(MAX_LOGGED_BODY + 1)));
+    }
+
+    #[test]
+    fn credentials_are_redacted() {
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", HeaderValue::from_static("secret"));
+        headers.insert("user-agent", HeaderValue::from_static("wunder"));
+        assert_eq!(redact_headers(&headers), vec![
+            ("authorization".to_string(), REDACTED.to_string()),
+            ("user-agent".to_string(), "wunder".to_string()),
+        ]);
+    }
+}
@@ -0,0 +1,272 @@
+//! Console and file logging, set up from the `log_settings` of the config and the verbosity flags.
+
+use std::{fs::{File, OpenOptions}, io::{self, Write}, path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+use chrono::{DateTime, Local, NaiveDate};
+use tracing_subscriber::{prelude::*, EnvFilter, Layer, Registry};
+
+use crate::{config::{LogFormat, LogRotation, LogSettings}, prelude::*};
+
+/// Sets up the console and file logs.
+///
+/// `RUST_LOG` overrides the configured level of both. Otherwise each `-v` makes the console one level more
+/// verbose than the configured level, and `quiet` turns it off; errors are still reported when the command fails.
+pub fn initialize(settings: &LogSettings, verbose: u8, quiet: bool) -> Result<()> {
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = vec![];
+
+    if !quiet {
+        let level = raise_level(&settings.level, verbose);
+        layers.push(tracing_subscriber::fmt::layer().with_filter(filter(&level, settings.trace_http)?).boxed());
+    }
+
+    if !settings.path.is_empty() {
+        let file = Arc::new(RotatingFile::open(settings)?);
+        let file_log = tracing_subscriber::fmt::layer()
+            .with_writer(file)
+            .with_ansi(false);
+        layers.push(match settings.format {
+            LogFormat::Text => file_log.with_filter(filter(&settings.level, settings.trace_http)?).boxed(),
+            LogFormat::Json => file_log.json().with_filter(filter(&settings.level, settings.trace_http)?).boxed(),
+        });
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .init();
+
+    Ok(())
+}
+
+/// The level `steps` levels more verbose than `level`.
+///
+/// A level made of directives (e.g. `info,hyper=warn`) can't be raised, it is replaced by info and up instead.
+fn raise_level(level: &str, steps: u8) -> String {
+    const LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+    if steps == 0 {
+        return level.to_string();
+    }
+    let from = LEVELS.iter()
+        .position(|l| l.eq_ignore_ascii_case(level.trim()))
+        .unwrap_or(2);
+    LEVELS[(from + steps as usize).min(LEVELS.len() - 1)].to_string()
+}
+
+/// Filter of a layer, `trace_http` lets the HTTP traces through whatever the level
+fn filter(level: &str, trace_http: bool) -> Result<EnvFilter> {
+    let filter = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(directives) if !directives.is_empty() => EnvFilter::try_new(&directives)
+            .map_err(|e| config::ConfigError::Message(format!("invalid {} {}: {}", EnvFilter::DEFAULT_ENV, directives, e)))?,
+        _ => EnvFilter::try_new(level)
+            .map_err(|e| config::ConfigError::Message(format!("invalid log level {}: {}", level, e)))?,
+    };
+    if !trace_http {
+        return Ok(filter);
+    }
+    let directive = format!("{}=debug", crate::http_log::TARGET).parse()
+        .map_err(|e| config::ConfigError::Message(format!("invalid HTTP trace directive: {}", e)))?;
+
+    Ok(filter.add_directive(directive))
+}
+
+/// The log file, moved aside when it gets too old or too big.
+///
+/// Rotated files are named after the file with the date (daily rotation) or the time (size rotation) they were
+/// rotated at appended, e.g. `wunder.log.2022-12-01`, and only the `max_files` most recent are kept.
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: LogRotation,
+    max_bytes: u64,
+    max_files: usize,
+    current: Mutex<Current>,
+}
+
+struct Current {
+    file: File,
+    size: u64,
+    /// Day the first line of the file was written
+    opened_on: NaiveDate,
+}
+
+impl RotatingFile {
+    pub fn open(settings: &LogSettings) -> Result<Self> {
+        let path = PathBuf::from(&settings.path);
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let current = Current::open(&path)?;
+
+        Ok(Self {
+            path,
+            rotation: settings.rotation,
+            max_bytes: settings.max_size_mb.saturating_mul(1024 * 1024),
+            max_files: settings.max_files,
+            current: Mutex::new(current),
+        })
+    }
+
+    /// Moves the current file aside when `incoming` bytes should not go in it
+    fn rotate_for(&self, current: &mut Current, incoming: usize) -> io::Result<()> {
+        let today = Local::now().date_naive();
+        let suffix = match self.rotation {
+            LogRotation::Never => return Ok(()),
+            LogRotation::Daily if current.opened_on < today => current.opened_on.format("%Y-%m-%d").to_string(),
+            LogRotation::Size if current.size > 0 && current.size + incoming as u64 > self.max_bytes => {
+                Local::now().format("%Y-%m-%dT%H%M%S%.3f").to_string()
+            },
+            _ => return Ok(()),
+        };
+
+        // rename replaces an existing file, which two rotations within the same millisecond would name alike
+        let rotated_name = |counter: usize| {
+            let mut rotated = self.path.as_os_str().to_owned();
+            match counter {
+                0 => rotated.push(format!(".{}", suffix)),
+                counter => rotated.push(format!(".{}.{}", suffix, counter)),
+            }
+            PathBuf::from(rotated)
+        };
+        let mut counter = 0;
+        while rotated_name(counter).exists() {
+            counter += 1;
+        }
+        std::fs::rename(&self.path, rotated_name(counter))?;
+        *current = Current::open(&self.path)?;
+        self.remove_old_files()
+    }
+
+    fn remove_old_files(&self) -> io::Result<()> {
+        let file_name = match self.path.file_name() {
+            Some(file_name) => format!("{}.", file_name.to_string_lossy()),
+            None => return Ok(()),
+        };
+        let folder = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let mut rotated = std::fs::read_dir(folder)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| matches!(path.file_name(), Some(name) if name.to_string_lossy().starts_with(&file_name)))
+            .collect::<Vec<_>>();
+        // the suffixes are dates and times, so the most recent files sort last
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.max_files);
+        for path in &rotated[..excess] {
+            std::fs::remove_file(path)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Current {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        let metadata = file.metadata()?;
+        let opened_on = match metadata.modified() {
+            Ok(modified) if metadata.len() > 0 => DateTime::<Local>::from(modified).date_naive(),
+            _ => Local::now().date_naive(),
+        };
+
+        Ok(Self { file, size: metadata.len(), opened_on })
+    }
+}
+
+impl Write for &RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        self.rotate_for(&mut current, buf.len())?;
+        let written = current.file.write(buf)?;
+        current.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.current.lock().unwrap_or_else(|e| e.into_inner()).file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(folder: &Path, rotation: LogRotation) -> LogSettings {
+        LogSettings {
+            path: folder.join("wunder.log").display().to_string(),
+            rotation,
+            max_size_mb: 0,
+            max_files: 2,
+            ..LogSettings::default()
+        }
+    }
+
+    fn files(folder: &Path) -> Vec<String> {
+        let mut files = std::fs::read_dir(folder).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn size_rotation_keeps_the_most_recent_files() {
+        let folder = std::env::temp_dir().join(format!("wunder-log-size-{}", std::process::id()));
+        let file = RotatingFile::open(&settings(&folder, LogRotation::Size)).unwrap();
+        for line in 0..4 {
+            (&file).write_all(format!("line {}\n", line).as_bytes()).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+
+        let files = files(&folder);
+        std::fs::remove_dir_all(&folder).unwrap();
+        // every line goes to a new file, as the limit is 0
+        assert_eq!(files.len(), 3, "{:?}", files);
+        assert_eq!(files[0], "wunder.log");
+        assert!(files[1..].iter().all(|name| name.starts_with("wunder.log.")));
+    }
+
+    #[test]
+    fn size_rotations_within_a_millisecond_keep_every_file() {
+        let folder = std::env::temp_dir().join(format!("wunder-log-burst-{}", std::process::id()));
+        let file = RotatingFile::open(&LogSettings { max_files: 10, ..settings(&folder, LogRotation::Size) }).unwrap();
+        for line in 0..4 {
+            (&file).write_all(format!("line {}\n", line).as_bytes()).unwrap();
+        }
+
+        let content = files(&folder).iter()
+            .map(|name| std::fs::read_to_string(folder.join(name)).unwrap())
+            .collect::<Vec<_>>()
+            .concat();
+        std::fs::remove_dir_all(&folder).unwrap();
+        for line in 0..4 {
+            assert!(content.contains(&format!("line {}\n", line)), "{}", content);
+        }
+    }
+
+    #[test]
+    fn verbosity_raises_the_configured_level() {
+        assert_eq!(raise_level("warn", 0), "warn");
+        assert_eq!(raise_level("warn", 1), "info");
+        assert_eq!(raise_level("ERROR", 2), "info");
+        assert_eq!(raise_level("debug", 3), "trace");
+        assert_eq!(raise_level("info,hyper=warn", 0), "info,hyper=warn");
+        assert_eq!(raise_level("info,hyper=warn", 2), "debug");
+    }
+
+    #[test]
+    fn daily_rotation_moves_yesterdays_file_aside() {
+        let folder = std::env::temp_dir().join(format!("wunder-log-daily-{}", std::process::id()));
+        let file = RotatingFile::open(&settings(&folder, LogRotation::Daily)).unwrap();
+        (&file).write_all(b"today\n").unwrap();
+        let yesterday = Local::now().date_naive().pred_opt().unwrap();
+        file.current.lock().unwrap().opened_on = yesterday;
+        (&file).write_all(b"tomorrow\n").unwrap();
+
+        let files = files(&folder);
+        let rotated = folder.join(format!("wunder.log.{}", yesterday.format("%Y-%m-%d")));
+        let content = (std::fs::read_to_string(&rotated).unwrap(), std::fs::read_to_string(folder.join("wunder.log")).unwrap());
+        std::fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(files.len(), 2, "{:?}", files);
+        assert_eq!(content, ("today\n".to_string(), "tomorrow\n".to_string()));
+    }
+}
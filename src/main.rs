@@ -7,40 +7,207 @@
 
 #[macro_use] extern crate serde_with_macros;
 
-use std::{fs::{File, OpenOptions}, sync::Arc, path::{PathBuf, Path}};
+use std::{fs::{File, OpenOptions}, path::{PathBuf, Path}};
 
-use clap::{Parser, Subcommand};
-use miette::IntoDiagnostic;
-use models::tracking::{TrackingRequest, XmlTrackingRequest};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use futures_util::StreamExt;
+use http_log::SendTraced;
+use models::tracking::{Carrier, CarrierResponse, TrackingFile, TrackingRequest, TrackingResult, TrackingStatus, OrderTracking};
 use reqwest::Body;
+use tokio::io::AsyncWriteExt;
 use tokio_util::codec::{FramedRead, BytesCodec};
-use tracing::{info, debug};
-use tracing_subscriber::{prelude::*, filter};
+use tracing::{info, debug, warn};
 use std::io::Write;
 
-use crate::{prelude::*, models::invoices::{OrderDocuments, OrderDocument}};
+use crate::{prelude::*, report::Counter, config::{AcceptanceDecision, AcceptanceMode, AcceptanceSettings, OrderFilters, UnknownFieldsMode}, models::{invoices::{OrderDocuments, OrderDocument, OrderDocumentListResponse}, incidents::{IncidentAction, IncidentRequest, OrderIncidents, Reason, ReasonResponse}, imports::{ImportResponse, ImportStatus}, offers::{OfferImportLine, OfferImportStatus, StockRecord, XmlStockUpdate}, products::ProductImportStatus, transactions::{BillingCycleRecord, BillingCycleResponse, TransactionLogResponse, TransactionRecord, TransactionState}, reconciliation::{ErpInvoiceRecord, ReconciliationRecord, ReconciliationStatus}, returns::{Return, ReturnResponse, ReturnUpdate, ReturnUpdateRecord}, deadlines::{DeadlineAlert, DeadlineNotification, NotifiedAlerts}, orders::{AcceptanceRequest, AdditionalField}, additional_fields::{AdditionalFieldDefinitionResponse, AdditionalFieldRecord, AdditionalFieldsRequest}, unknown::{to_xml, StripUnknownFields}}};
 
 mod models;
 mod prelude;
 mod config;
 mod error;
+mod http_log;
+mod logging;
+mod report;
+mod mock;
+
+/// Documents the exit statuses of [`crate::error::ExitStatus`]
+const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0  success
+  1  other error
+  2  invalid arguments
+  3  missing or invalid config
+  4  input file not found
+  5  invalid input file or response
+  6  request rejected by the marketplace (HTTP 4xx), don't retry
+  7  marketplace failed or unreachable (HTTP 5xx, network), retry later
+  8  part of a batch failed, see the result or report file
+  9  ran, but found discrepancies or deadlines at risk
+ 10  import still running on the marketplace, check it before pushing again";
 
 #[derive(Debug, Parser)]
-#[command(about, version, author, long_about = None)]
+#[command(about, version, author, long_about = None, after_help = EXIT_STATUS_HELP)]
 struct CliArgs {
+    /// Print the requests that would change data instead of sending them, and write exports to the dry-run path
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    /// Write a JSON report of the run to this file, or to stdout with `-`
+    #[arg(long, global = true, value_name = "PATH")]
+    pub report: Option<String>,
+    /// Log more on the console: each -v is one level more verbose than the configured level
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+    /// Log nothing on the console, the log file is still written
+    #[arg(short, long, global = true)]
+    pub quiet: bool,
+    /// Log the HTTP requests and responses, without credentials and customer details
+    #[arg(long, global = true)]
+    pub trace_http: bool,
     #[command(subcommand)]
     pub command: CliSubcommand
 }
 
 #[derive(Debug, Subcommand)]
 enum CliSubcommand {
-    PullOrders,
+    PullOrders {
+        /// Only pull orders in these states. Defaults to the configured state codes.
+        #[arg(long = "state", value_delimiter = ',')]
+        order_state_codes: Vec<String>,
+        #[command(flatten)]
+        filters: Box<OrderFilters>,
+    },
+    /// Accept orders held for review
+    AcceptOrders {
+        #[arg(required = true)]
+        order_ids: Vec<String>,
+    },
+    /// Refuse orders held for review
+    RefuseOrders {
+        #[arg(required = true)]
+        order_ids: Vec<String>,
+    },
+    /// Fetch specific orders
+    GetOrder {
+        #[arg(required = true)]
+        order_ids: Vec<String>,
+        #[arg(long, value_enum, default_value_t = OrderFormat::Xml)]
+        format: OrderFormat,
+        /// Write the orders to the configured order path instead of stdout
+        #[arg(long)]
+        save: bool,
+    },
+    /// Push tracking info from an XML file of one or more <tracking> records, or from a CSV file
     PushTrackingInfo {
+        /// CSV files have the columns order_id, carrier_code, carrier_name, carrier_url, tracking_number
         tracking_file: String,
     },
     PushInvoice {
         invoice_file: String,
     },
+    ListIncidents,
+    PullIncidents,
+    OpenIncident {
+        order_id: String,
+        order_line_id: String,
+        reason_code: String,
+    },
+    CloseIncident {
+        order_id: String,
+        order_line_id: String,
+        reason_code: String,
+    },
+    PushOffers {
+        stock_file: String,
+    },
+    PushProducts {
+        product_file: String,
+    },
+    PullTransactions {
+        /// Only export entries created from this date (ISO 8601)
+        #[arg(long, value_parser = config::parse_date)]
+        from: Option<String>,
+        /// Only export entries created until this date (ISO 8601)
+        #[arg(long, value_parser = config::parse_date)]
+        to: Option<String>,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Start from where the previous run stopped, unless --from is given
+        #[arg(long)]
+        resume: bool,
+    },
+    Reconcile {
+        /// CSV export of the invoiced amounts per order (order_id, invoiced_amount and optionally commission)
+        erp_file: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+    },
+    ListReturns,
+    PullReturns,
+    PushReturnUpdates {
+        /// CSV file of return line updates (return_id, return_line_id, status, quantity)
+        return_file: String,
+    },
+    /// Update order and order line additional fields
+    SetAdditionalFields {
+        /// CSV file of additional fields (order_id, order_line_id, code, type, value), the type may be left empty
+        field_file: String,
+    },
+    CheckDeadlines {
+        /// Hours before the deadline from which an order is reported. Defaults to the configured window.
+        #[arg(long)]
+        window: Option<i64>,
+        /// Keep running and check again every INTERVAL seconds
+        #[arg(long)]
+        interval: Option<u64>,
+    },
+    /// Serve fixture orders on a local stand-in for the marketplace API
+    MockServer {
+        /// Folder of JSON order fixtures
+        fixtures: String,
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: std::net::SocketAddr,
+        #[arg(long, default_value_t = 10)]
+        page_size: usize,
+        /// Fail every nth request
+        #[arg(long)]
+        fail_every: Option<usize>,
+        #[arg(long, default_value_t = 500)]
+        fail_status: u16,
+        /// Only fail requests whose path starts with this prefix
+        #[arg(long)]
+        fail_path: Option<String>,
+    },
+    PullDocuments {
+        /// Orders to fetch documents for. Defaults to the orders in the configured state codes.
+        order_ids: Vec<String>,
+        /// Only fetch documents of this type (e.g. CUSTOMER_INVOICE)
+        #[arg(long)]
+        type_code: Option<String>,
+        /// Only list the documents, without downloading them
+        #[arg(long)]
+        list: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum OrderFormat {
+    Xml,
+    Json,
 }
 
 const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
@@ -64,110 +231,736 @@ const DOCUMENT_FORMATS: [&str; 17] = [
     "mp4",
 ];
 
-fn initialize_logging() -> Result<()> {
-    let stdout_log = tracing_subscriber::fmt::layer();
-    
-    let file = OpenOptions::new()
+/// Like `error_for_status`, but keeps what the API said went wrong in an [`ApiError`](crate::error::ApiError)
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response> {
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(response);
+    }
+    let url = response.url().to_string();
+    let body = response.text().await.unwrap_or_default();
+    debug!("Error response from {}: {}", url, http_log::redact_body(body.as_bytes()));
+    Err(crate::error::WunderError::Api(Box::new(crate::error::ApiError::new(status, url, &body))))
+}
+
+/// Sends a request that changes something on the other end.
+///
+/// In dry-run mode the request is printed instead of sent, and `None` is returned.
+async fn send_mutation(config: &crate::config::Config, request: reqwest::RequestBuilder) -> Result<Option<reqwest::Response>> {
+    if !config.dry_run {
+        return Ok(Some(check_status(request.send_traced(config).await?).await?));
+    }
+
+    let request = request.build()?;
+    print_request(&request);
+    if let Some(body) = request.body() {
+        match body.as_bytes() {
+            Some(bytes) => println!("\n{}", String::from_utf8_lossy(bytes)),
+            None => println!("\n<streamed body>"),
+        }
+    }
+    println!();
+    info!("Dry run, did not send {} {}", request.method(), request.url());
+
+    Ok(None)
+}
+
+/// Prints the request line and headers of a request that is not sent, without credentials
+fn print_request(request: &reqwest::Request) {
+    println!("{} {}", request.method(), request.url());
+    for (name, value) in request.headers() {
+        println!("{}: {}", name, http_log::header_value(name, value));
+    }
+}
+
+/// A field of a multipart upload
+struct UploadPart {
+    name: &'static str,
+    /// Name of the uploaded file, `None` for plain fields
+    file_name: Option<String>,
+    mime: Option<&'static str>,
+    content: UploadContent,
+}
+
+enum UploadContent {
+    Bytes(Vec<u8>),
+    /// A file on disk, streamed unless in dry-run mode
+    File(PathBuf),
+}
+
+impl UploadPart {
+    /// The file at `path`, uploaded as `file_name`
+    fn file(name: &'static str, path: &Path, file_name: String) -> Self {
+        Self { name, file_name: Some(file_name), mime: None, content: UploadContent::File(path.to_path_buf()) }
+    }
+
+    /// A file built in memory
+    fn bytes(name: &'static str, file_name: &str, bytes: Vec<u8>) -> Self {
+        Self { name, file_name: Some(file_name.to_string()), mime: None, content: UploadContent::Bytes(bytes) }
+    }
+
+    fn text(name: &'static str, value: &str) -> Self {
+        Self { name, file_name: None, mime: None, content: UploadContent::Bytes(value.as_bytes().to_vec()) }
+    }
+
+    fn json<T: serde::Serialize>(name: &'static str, value: &T) -> Result<Self> {
+        Ok(Self { name, file_name: None, mime: Some("application/json"), content: UploadContent::Bytes(serde_json::to_vec(value)?) })
+    }
+}
+
+/// Sends a multipart upload that changes something on the other end, see [`send_mutation`].
+///
+/// Files are streamed from disk. In dry-run mode they are read instead, so that every part can be printed.
+async fn send_upload(config: &crate::config::Config, request: reqwest::RequestBuilder, parts: Vec<UploadPart>) -> Result<Option<reqwest::Response>> {
+    let mut form = reqwest::multipart::Form::new();
+    let mut printed = vec![];
+    for UploadPart { name, file_name, mime, content } in parts {
+        let mut part = match content {
+            UploadContent::File(path) if !config.dry_run => {
+                let file = tokio::fs::File::open(&path).await?;
+                reqwest::multipart::Part::stream(Body::wrap_stream(FramedRead::new(file, BytesCodec::new())))
+            },
+            content => {
+                let bytes = match content {
+                    UploadContent::File(path) => tokio::fs::read(&path).await?,
+                    UploadContent::Bytes(bytes) => bytes,
+                };
+                if config.dry_run {
+                    let description = file_name.as_deref().map(|file_name| format!("{} ({})", name, file_name)).unwrap_or_else(|| name.to_string());
+                    let content = match String::from_utf8(bytes.clone()) {
+                        Ok(text) => text,
+                        Err(_) => format!("<{} bytes>", bytes.len()),
+                    };
+                    printed.push((description, content));
+                }
+                reqwest::multipart::Part::bytes(bytes)
+            },
+        };
+        if let Some(file_name) = file_name {
+            part = part.file_name(file_name);
+        }
+        if let Some(mime) = mime {
+            part = part.mime_str(mime)?;
+        }
+        form = form.part(name, part);
+    }
+    if !config.dry_run {
+        return send_mutation(config, request.multipart(form)).await;
+    }
+
+    let request = request.multipart(form).build()?;
+    print_request(&request);
+    for (description, content) in printed {
+        println!("\n--- {}\n{}", description, content);
+    }
+    println!();
+    info!("Dry run, did not send {} {}", request.method(), request.url());
+
+    Ok(None)
+}
+
+/// Spaces out requests so that no more than the configured number start every second.
+struct RateLimiter {
+    interval: Option<std::time::Duration>,
+    next: tokio::sync::Mutex<tokio::time::Instant>,
+}
+
+impl RateLimiter {
+    fn new(requests_per_second: Option<f64>) -> Self {
+        Self {
+            interval: requests_per_second.filter(|rate| *rate > 0.0).map(|rate| std::time::Duration::from_secs_f64(1.0 / rate)),
+            next: tokio::sync::Mutex::new(tokio::time::Instant::now()),
+        }
+    }
+
+    /// Waits for the next free slot, right away when there is no limit
+    async fn wait(&self) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(tokio::time::Instant::now());
+            *next = slot + interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Extracts the `rel="next"` target from a `Link` header, if there is one.
+fn next_page_url(headers: &reqwest::header::HeaderMap) -> Option<String> {
+    let link = headers.get(reqwest::header::LINK)?.to_str().ok()?;
+    link.split(',')
+        .find(|part| part.contains("rel=\"next\""))
+        .and_then(|part| {
+            let start = part.find('<')?;
+            let end = part.find('>')?;
+            Some(part[start + 1..end].to_string())
+        })
+}
+
+async fn fetch_orders(config: &crate::config::Config, client: &reqwest::Client, query: &[(&str, String)]) -> Result<Vec<models::orders::Order>> {
+    let mut orders = Vec::new();
+    let mut url = Some(format!("{}/api/orders", &config.base_url));
+    let mut first_page = true;
+
+    while let Some(current_url) = url {
+        let mut request = client.get(&current_url)
+            .header("Authorization", &config.api_key)
+            .header("User-Agent", USER_AGENT);
+        // the next page link already carries the query
+        if first_page {
+            request = request.query(query);
+            first_page = false;
+        }
+        let response = request
+            .send_traced(config)
+            .await?;
+        let response = check_status(response).await?;
+        url = next_page_url(response.headers());
+        let mut order_response: models::orders::OrderResponse = models::tolerant::from_value(
+            response.json().await?,
+            config.pull_order_settings.tolerant,
+        )?;
+        if config.unknown_fields == UnknownFieldsMode::Strip {
+            order_response.orders.strip_unknown_fields();
+        }
+        debug!("Fetched {} orders", order_response.total_count);
+        config.report.count(Counter::Fetched, order_response.orders.len());
+        orders.extend(order_response.orders);
+    }
+
+    Ok(orders)
+}
+
+async fn fetch_reasons(config: &crate::config::Config, client: &reqwest::Client, reason_type: &str) -> Result<Vec<Reason>> {
+    let response = client.get(format!("{}/api/reasons/{}", &config.base_url, reason_type))
+        .header("Authorization", &config.api_key)
+        .header("User-Agent", USER_AGENT)
+        .send_traced(config)
+        .await?;
+    let response = check_status(response).await?
+        .json::<ReasonResponse>()
+        .await?;
+
+    Ok(response.reasons)
+}
+
+/// Builds the name of a file belonging to an order, e.g. `20221201-093000-1234-A-GetOrders_Response.xml`.
+fn order_file_name(order_id: &str, suffix: &str) -> String {
+    format!("{}-{}-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"), order_id, suffix)
+}
+
+/// Makes a name sent by the marketplace usable as part of a file name, `None` when nothing usable is left.
+fn safe_file_name(name: &str) -> Option<String> {
+    let name = name.replace(['/', '\\', '\0'], "_");
+    match name.trim() {
+        "" | "." | ".." => None,
+        _ => Some(name),
+    }
+}
+
+/// Whether the default decision must be taken now to beat the marketplace's auto-refusal.
+fn acceptance_deadline_near(settings: &AcceptanceSettings, order: &models::orders::Order) -> bool {
+    match chrono::DateTime::parse_from_rfc3339(&order.created_date) {
+        Ok(created_date) => {
+            let deadline = created_date + chrono::Duration::hours(settings.acceptance_delay_hours);
+            deadline - chrono::Duration::hours(settings.safety_margin_hours) <= chrono::Utc::now()
+        },
+        // an order whose deadline is unknown is left to the reviewers rather than decided by default
+        Err(e) => {
+            warn!("Could not parse creation date {} of order {}, holding it for review: {}", order.created_date, order.order_id, e);
+            false
+        },
+    }
+}
+
+/// The order id in the name of a file written with [`order_file_name`] and a suffix without `-`.
+fn order_file_id(file_name: &str) -> Option<&str> {
+    let timestamp = file_name.get(..15)?;
+    chrono::NaiveDateTime::parse_from_str(timestamp, "%Y%m%d-%H%M%S").ok()?;
+    let (order_id, _suffix) = file_name.get(16..)?.rsplit_once('-')?;
+    Some(order_id)
+}
+
+/// Finds the files of an order in a folder written with [`order_file_name`].
+fn find_order_files(path: &Path, order_id: &str) -> Result<Vec<PathBuf>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let mut files = vec![];
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if order_file_id(&entry.file_name().to_string_lossy()) == Some(order_id) {
+            files.push(entry.path());
+        }
+    }
+
+    Ok(files)
+}
+
+async fn hold_for_review(config: &crate::config::Config, order: &models::orders::Order) -> Result<()> {
+    let review_path = PathBuf::from(&config.pull_order_settings.acceptance.review_path);
+    if !review_path.exists() {
+        tokio::fs::create_dir_all(&review_path).await?;
+    }
+    if !find_order_files(&review_path, &order.order_id)?.is_empty() {
+        debug!("Order {} is already waiting for review", order.order_id);
+        return Ok(());
+    }
+
+    let review_file = review_path.join(order_file_name(&order.order_id, "GetOrders_Response.xml"));
+    let mut file = File::create(&review_file)?;
+    let order_xml = to_xml("Order", order)?;
+    file.write_all(order_xml.as_bytes())?;
+    info!("Holding order {} for review in {}", order.order_id, review_file.display());
+    config.report.count(Counter::HeldForReview, 1);
+
+    Ok(())
+}
+
+/// Accepts or refuses an order, returns the line to add to the decisions log, none in dry-run mode.
+async fn decide_order(config: &crate::config::Config, client: &reqwest::Client, order: &models::orders::Order, decision: AcceptanceDecision, reason: &str) -> Result<Option<String>> {
+    let accepted = decision == AcceptanceDecision::Accept;
+    let request = client.put(format!("{}/api/orders/{}/accept", &config.base_url, order.order_id))
+        .header("Authorization", &config.api_key)
+        .header("User-Agent", USER_AGENT)
+        .json(&AcceptanceRequest::for_order(order, accepted));
+    if send_mutation(config, request).await?.is_none() {
+        // nothing was decided, the review folder and decisions log are left as they are
+        info!(order_id = %order.order_id, ?decision, reason, "Dry run, would have {} order {}", if accepted { "accepted" } else { "refused" }, order.order_id);
+        return Ok(None);
+    }
+    info!(order_id = %order.order_id, ?decision, reason, "{} order {}", if accepted { "Accepted" } else { "Refused" }, order.order_id);
+    config.report.count(if accepted { Counter::Accepted } else { Counter::Refused }, 1);
+
+    let review_path = PathBuf::from(&config.pull_order_settings.acceptance.review_path);
+    for review_file in find_order_files(&review_path, &order.order_id)? {
+        tokio::fs::remove_file(&review_file).await?;
+    }
+
+    Ok(Some(format!("{}\t{}\t{:?}\t{}", chrono::Local::now().to_rfc3339(), order.order_id, decision, reason)))
+}
+
+/// Appends lines to the decisions log, from a single task so that lines never interleave
+fn log_decisions(config: &crate::config::Config, lines: &[String]) -> Result<()> {
+    if lines.is_empty() {
+        return Ok(());
+    }
+    let review_path = PathBuf::from(&config.pull_order_settings.acceptance.review_path);
+    if !review_path.exists() {
+        std::fs::create_dir_all(&review_path)?;
+    }
+    let mut decisions = OpenOptions::new()
         .append(true)
         .create(true)
-        .open("wunder.log")?;
-    let file_log = tracing_subscriber::fmt::layer()
-        .with_writer(Arc::new(file))
-        .with_ansi(false);
-
-    tracing_subscriber::registry()
-        .with(stdout_log)
-        .with(file_log)
-        .with(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
-    
+        .open(review_path.join("decisions.log"))?;
+    for line in lines {
+        writeln!(decisions, "{}", line)?;
+    }
+
     Ok(())
 }
 
-async fn pull_orders(config: &crate::config::Config) -> Result<()> {
+/// Writes an order accepted by a reviewer to the order path, like the orders accepted when pulled
+async fn write_accepted_order(config: &crate::config::Config, order: &models::orders::Order) -> Result<()> {
     let order_path = PathBuf::from(&config.pull_order_settings.order_path);
     if !order_path.exists() {
         tokio::fs::create_dir_all(&order_path).await?;
     }
+    let order_file = order_path.join(order_file_name(&order.order_id, "GetOrders_Response.xml"));
+    tokio::fs::write(&order_file, to_xml("Order", order)?).await?;
+    info!("Wrote order {} to {}", order.order_id, order_file.display());
+    config.report.count(Counter::Written, 1);
+
+    Ok(())
+}
+
+async fn review_orders(config: &crate::config::Config, order_ids: Vec<String>, decision: AcceptanceDecision) -> Result<()> {
+    let client = reqwest::Client::new();
+    let mut orders = vec![];
+    for chunk in order_ids.chunks(100) {
+        orders.extend(fetch_orders(config, &client, &[("order_ids", chunk.join(","))]).await?);
+    }
+    let mut decisions = vec![];
+    let mut result = Ok(());
+    for order_id in &order_ids {
+        let order = match orders.iter().find(|order| &order.order_id == order_id) {
+            Some(order) if order.order_state == "WAITING_ACCEPTANCE" => order,
+            _ => {
+                result = Err(crate::error::WunderError::OrderNotWaitingAcceptance(order_id.clone()));
+                break;
+            },
+        };
+        let decided = match decide_order(config, &client, order, decision, "manual").await {
+            Ok(line) => {
+                decisions.extend(line);
+                match decision {
+                    AcceptanceDecision::Accept => write_accepted_order(config, order).await,
+                    AcceptanceDecision::Refuse => Ok(()),
+                }
+            },
+            Err(e) => Err(e),
+        };
+        if let Err(e) = decided {
+            result = Err(e);
+            break;
+        }
+    }
+    // the orders decided before a failure are logged all the same
+    log_decisions(config, &decisions)?;
+
+    result
+}
+
+/// Fetches the additional fields defined on the marketplace, from the cache file while it is fresh.
+async fn fetch_additional_field_definitions(config: &crate::config::Config, client: &reqwest::Client) -> Result<AdditionalFieldDefinitionResponse> {
+    let settings = &config.additional_field_settings;
+    let cache_file = PathBuf::from(&settings.cache_file);
+    let age = std::fs::metadata(&cache_file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok());
+    if matches!(age, Some(age) if age.as_secs() < settings.cache_ttl_hours * 3600) {
+        debug!("Using the additional field definitions cached in {}", cache_file.display());
+        return Ok(serde_json::from_reader(File::open(&cache_file)?)?);
+    }
+
+    let response = client.get(format!("{}/api/additional_fields", &config.base_url))
+        .header("Authorization", &config.api_key)
+        .header("User-Agent", USER_AGENT)
+        .query(&[("entities", "ORDER,ORDER_LINE")])
+        .send_traced(config)
+        .await?;
+    let mut definitions = check_status(response).await?
+        .json::<AdditionalFieldDefinitionResponse>()
+        .await?;
+    if config.unknown_fields == UnknownFieldsMode::Strip {
+        definitions.additional_fields.strip_unknown_fields();
+    }
+    if let Some(cache_path) = cache_file.parent().filter(|path| !path.as_os_str().is_empty() && !path.exists()) {
+        tokio::fs::create_dir_all(cache_path).await?;
+    }
+    serde_json::to_writer_pretty(File::create(&cache_file)?, &definitions)?;
+    debug!("Cached {} additional field definitions in {}", definitions.additional_fields.len(), cache_file.display());
+
+    Ok(definitions)
+}
+
+async fn pull_orders(config: &crate::config::Config, order_state_codes: String, filters: OrderFilters) -> Result<()> {
+    let settings = &config.pull_order_settings;
+    let order_path = PathBuf::from(&settings.order_path);
+    if !order_path.exists() {
+        tokio::fs::create_dir_all(&order_path).await?;
+    }
 
     let client = reqwest::Client::new();
-    debug!("Fetching orders that have the following state codes: {}", config.pull_order_settings.order_state_codes());
-    let mut url = Some(format!("{}/api/orders", &config.base_url));
-    
-    while url.is_some() {
-        let response = client.get(url.as_ref().unwrap())
-            .header("Authorization", &config.api_key)
-            .header("User-Agent", USER_AGENT)
-            .query(&[("order_state_codes", config.pull_order_settings.order_state_codes())])
-            .send()
-            .await?
-            .error_for_status()?;
-        url = response.headers()
-            .get(reqwest::header::LINK)
-            .map(|l| l.to_str().unwrap().to_string());
-        let order_response = response.json::<models::orders::OrderResponse>().await?;
-        debug!("Fetched {} orders", order_response.total_count);
-        for order in order_response.orders {
-            if &order.order_state == "WAITING_ACCEPTANCE" {
-                client.put(format!("{}/api/orders/{}/accept", &config.base_url, order.order_id))
-                    .header("Authorization", &config.api_key)
-                    .header("User-Agent", USER_AGENT)
-                    .send()
-                    .await?
-                    .error_for_status()?;
-            }
+    debug!("Fetching orders that have the following state codes: {}", order_state_codes);
+    let mut query = filters.query();
+    query.push(("order_state_codes", order_state_codes));
+    let definitions = if config.additional_field_settings.validate_orders {
+        Some(fetch_additional_field_definitions(config, &client).await?)
+    } else {
+        None
+    };
+    let limiter = RateLimiter::new(settings.requests_per_second);
+
+    let mut orders = vec![];
+    for order in fetch_orders(config, &client, &query).await? {
+        if !filters.matches(&order) {
+            debug!("Skipping order {} filtered out locally", order.order_id);
+            config.report.count(Counter::Skipped, 1);
+            continue;
+        }
+        // bad additional field values are flagged, the order is exported as is
+        for problem in models::additional_fields::check_order(&order, definitions.as_ref()) {
+            warn!("Invalid additional field on {}", problem);
+        }
+        orders.push(order);
+    }
+
+    // orders are settled and written concurrently, but reported in the order they were fetched in
+    let mut processed = futures_util::stream::iter(orders)
+        .map(|order| {
+            let order_id = order.order_id.clone();
+            let order_file = order_path.join(order_file_name(&order.order_id, "GetOrders_Response.xml"));
+            let processing = process_order(config, &client, &limiter, order, order_file);
+            async move { (order_id, processing.await) }
+        })
+        .buffered(settings.concurrency.max(1));
+    // a failed order does not stop the others, which may already have been accepted
+    let mut result = Ok(());
+    while let Some((order_id, processed)) = processed.next().await {
+        let logged = match processed.decision {
+            Some(decision) => log_decisions(config, &[decision]),
+            None => Ok(()),
+        };
+        match logged.and(processed.written) {
+            Ok(Some(order_file)) => info!("Wrote order {} to {}", order_id, order_file.display()),
+            Ok(None) => (),
+            Err(e) => {
+                config.report.failure(&order_id, &e);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            },
+        }
+    }
+
+    result
+}
+
+/// What became of a pulled order
+struct ProcessedOrder {
+    /// Line of the decisions log, when the order was accepted or refused
+    decision: Option<String>,
+    /// File the order was written to, none when it was refused or held for review
+    written: Result<Option<PathBuf>>,
+}
+
+/// Settles an order waiting for acceptance and writes it to `order_file` unless it was refused or held for review.
+async fn process_order(config: &crate::config::Config, client: &reqwest::Client, limiter: &RateLimiter, order: models::orders::Order, order_file: PathBuf) -> ProcessedOrder {
+    let mut decision_line = None;
+    if &order.order_state == "WAITING_ACCEPTANCE" {
+        let acceptance = &config.pull_order_settings.acceptance;
+        let decision = match acceptance.mode {
+            AcceptanceMode::Accept => Some((AcceptanceDecision::Accept, "auto")),
+            AcceptanceMode::Review if acceptance_deadline_near(acceptance, &order) => Some((acceptance.default_decision, "deadline")),
+            AcceptanceMode::Review => None,
+        };
+        match decision {
+            Some((decision, reason)) => {
+                limiter.wait().await;
+                decision_line = match decide_order(config, client, &order, decision, reason).await {
+                    Ok(line) => line,
+                    Err(e) => return ProcessedOrder { decision: None, written: Err(e) },
+                };
+                if decision == AcceptanceDecision::Refuse {
+                    return ProcessedOrder { decision: decision_line, written: Ok(None) };
+                }
+            },
+            None => {
+                let held = hold_for_review(config, &order).await;
+                return ProcessedOrder { decision: None, written: held.map(|()| None) };
+            },
+        }
+    }
+
+    let written = match to_xml("Order", &order) {
+        Ok(order_xml) => tokio::fs::write(&order_file, order_xml).await.map_err(Into::into),
+        Err(e) => Err(e.into()),
+    };
+    if written.is_ok() {
+        config.report.count(Counter::Written, 1);
+    }
+
+    ProcessedOrder { decision: decision_line, written: written.map(|()| Some(order_file)) }
+}
+
+async fn get_orders(config: &crate::config::Config, order_ids: Vec<String>, format: OrderFormat, save: bool) -> Result<()> {
+    let client = reqwest::Client::new();
+    let mut orders = vec![];
+    for chunk in order_ids.chunks(100) {
+        orders.extend(fetch_orders(config, &client, &[("order_ids", chunk.join(","))]).await?);
+    }
 
-            let order_file = order_path.join(format!("{}-{}-GetOrders_Response.xml", chrono::Local::now().format("%Y%m%d-%H%M%S"), order.order_id));
+    let order_path = PathBuf::from(&config.pull_order_settings.order_path);
+    if save && !order_path.exists() {
+        tokio::fs::create_dir_all(&order_path).await?;
+    }
+    for order in &orders {
+        let (content, file_suffix) = match format {
+            OrderFormat::Xml => (to_xml("Order", order)?, "GetOrders_Response.xml"),
+            OrderFormat::Json => (serde_json::to_string_pretty(order)?, "GetOrders_Response.json"),
+        };
+        if save {
+            let order_file = order_path.join(order_file_name(&order.order_id, file_suffix));
             let mut file = File::create(&order_file)?;
-            let order_xml = quick_xml::se::to_string(&order)?;
-            file.write_all(order_xml.as_bytes())?;
+            file.write_all(content.as_bytes())?;
             info!("Wrote order {} to {}", order.order_id, order_file.display());
+        } else {
+            println!("{}", content);
         }
     }
 
+    let missing = order_ids.into_iter()
+        .filter(|order_id| !orders.iter().any(|order| &order.order_id == order_id))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(crate::error::WunderError::OrdersNotFound(missing.join(", ")));
+    }
+
     Ok(())
 }
 
+async fn fetch_carriers(config: &crate::config::Config, client: &reqwest::Client) -> Result<Vec<Carrier>> {
+    let response = client.get(format!("{}/api/shipping/carriers", &config.base_url))
+        .header("Authorization", &config.api_key)
+        .header("User-Agent", USER_AGENT)
+        .send_traced(config)
+        .await?;
+    let response = check_status(response).await?
+        .json::<CarrierResponse>()
+        .await?;
+    debug!("Fetched {} carriers", response.carriers.len());
+
+    Ok(response.carriers)
+}
+
+/// Pushes the tracking info of an order, returns whether it was sent, not in dry-run mode.
+async fn send_tracking_info(config: &crate::config::Config, client: &reqwest::Client, order_id: &str, tracking_request: TrackingRequest) -> Result<bool> {
+    let request = client.post(format!("{}/api/orders/{}/tracking", &config.base_url, order_id))
+        .header("Authorization", &config.api_key)
+        .header("User-Agent", USER_AGENT)
+        .json(&tracking_request);
+    let response = send_mutation(config, request).await?;
+    let sent = response.is_some();
+    if let Some(response) = response {
+        debug!("Tracking push response: {}", response.text().await?);
+        info!("Pushed tracking info for order {}, verifying...", order_id);
+    }
+    let request = client.get(format!("{}/api/orders/{}/ship", &config.base_url, order_id))
+        .header("Authorization", &config.api_key)
+        .header("User-Agent", USER_AGENT);
+    send_mutation(config, request).await?;
+    if !sent {
+        info!("Dry run, would have pushed and verified tracking info for order {}", order_id);
+        return Ok(false);
+    }
+    info!("Verified tracking info for order {}", order_id);
+    config.report.count(Counter::Pushed, 1);
+
+    Ok(true)
+}
+
+/// Short description of why a record of a batch failed, for the result file
+fn tracking_failure(error: &crate::error::WunderError) -> String {
+    match error {
+        crate::error::WunderError::InvalidTrackingFile(error) => error.label.clone(),
+        error => error.to_string(),
+    }
+}
+
 async fn push_tracking_info<P>(config: &crate::config::Config, tracking_file: P) -> Result<()>
 where
     P: AsRef<Path>
 {
     debug!("Pushing tracking info from {}", tracking_file.as_ref().display());
-    let buf_reader = std::io::BufReader::new(File::open(tracking_file)?);
-    let tracking_request: XmlTrackingRequest = quick_xml::de::from_reader(buf_reader)?;
-    let order_id = tracking_request.order_id.clone();
+    let extension = tracking_file.as_ref().extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
     let client = reqwest::Client::new();
-    let response = client.post(format!("{}/api/orders/{}/tracking", &config.base_url, &tracking_request.order_id))
-        .header("Authorization", &config.api_key)
-        .header("User-Agent", USER_AGENT)
-        .json(&Into::<TrackingRequest>::into(tracking_request))
-        .send()
-        .await?
-        .error_for_status()?;
-    debug!("Tracking push response: {}", response.text().await?);
-    info!("Pushed tracking info for order {}, verifying...", &order_id);
-    client.get(format!("{}/api/orders/{}/ship", &config.base_url, &order_id))
-        .header("Authorization", &config.api_key)
-        .header("User-Agent", USER_AGENT)
-        .send()
-        .await?
-        .error_for_status()?;
-    info!("Verified tracking info for order {}", &order_id);
+
+    // every record is checked first, the valid ones are then pushed while the invalid ones are reported
+    let mut checked: Vec<(String, usize, Result<OrderTracking>)> = vec![];
+    if extension == "csv" {
+        let carriers = fetch_carriers(config, &client).await?;
+        let mut reader = csv::Reader::from_path(tracking_file.as_ref())?;
+        let headers = reader.headers()?.clone();
+        let order_id_column = headers.iter().position(|header| header == "order_id");
+        for (index, record) in reader.records().enumerate() {
+            // the header is line 1
+            let line = index + 2;
+            let record = record?;
+            let checked_record = match record.deserialize::<OrderTracking>(Some(&headers)) {
+                Ok(request) => match request.check_carrier(&carriers) {
+                    Ok(()) => (request.order_id.clone(), line, Ok(request)),
+                    Err(problem) => (request.order_id, line, Err(crate::error::WunderError::InvalidTrackingRecord(problem.message))),
+                },
+                // the order id is reported when there is one
+                Err(e) => {
+                    let order_id = order_id_column.and_then(|column| record.get(column)).unwrap_or_default();
+                    (order_id.to_string(), line, Err(e.into()))
+                },
+            };
+            checked.push(checked_record);
+        }
+    } else {
+        let tracking_file = TrackingFile::read(tracking_file.as_ref())?;
+        let records = tracking_file.records();
+        if let [record] = records.as_slice() {
+            // a single request keeps failing with a diagnostic pointing into the file
+            let tracking_request = record.parse()?;
+            let carriers = fetch_carriers(config, &client).await?;
+            record.check_carrier(&tracking_request, &carriers)?;
+            let order_id = tracking_request.order_id.clone();
+            return send_tracking_info(config, &client, &order_id, tracking_request.into()).await.map(|_| ());
+        }
+        let carriers = fetch_carriers(config, &client).await?;
+        for record in records {
+            let checked_record = match record.parse() {
+                Ok(request) => match record.check_carrier(&request, &carriers) {
+                    Ok(()) => (request.order_id.clone(), record.line(), Ok(request)),
+                    Err(e) => (request.order_id, record.line(), Err(e)),
+                },
+                Err(e) => (String::new(), record.line(), Err(e)),
+            };
+            checked.push(checked_record);
+        }
+    }
+
+    let settings = &config.tracking_settings;
+    let results = futures_util::stream::iter(checked)
+        .map(|(order_id, line, request)| {
+            let client = &client;
+            async move {
+                let outcome = match request {
+                    Ok(request) => send_tracking_info(config, client, &order_id, request.into()).await,
+                    Err(e) => Err(e),
+                };
+                match outcome {
+                    Ok(true) => TrackingResult { order_id, line, status: TrackingStatus::Pushed, message: String::new() },
+                    Ok(false) => TrackingResult { order_id, line, status: TrackingStatus::NotSent, message: "dry run".to_string() },
+                    Err(e) => {
+                        let message = tracking_failure(&e);
+                        warn!("Could not push tracking info at line {}: {}", line, message);
+                        let item = if order_id.is_empty() { format!("line {}", line) } else { order_id.clone() };
+                        config.report.failure(&item, &e);
+                        TrackingResult { order_id, line, status: TrackingStatus::Failed, message }
+                    },
+                }
+            }
+        })
+        .buffered(settings.concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    let failed = results.iter().filter(|result| result.status == TrackingStatus::Failed).count();
+    let pushed = results.iter().filter(|result| result.status == TrackingStatus::Pushed).count();
+
+    let report_path = PathBuf::from(&settings.report_path);
+    if !report_path.exists() {
+        tokio::fs::create_dir_all(&report_path).await?;
+    }
+    let result_file = report_path.join(format!("{}-Tracking.csv", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    write_records(&result_file, &results, ExportFormat::Csv)?;
+    info!("Pushed tracking info for {} of {} orders, results written to {}", pushed, results.len(), result_file.display());
+
+    if failed > 0 {
+        return Err(crate::error::WunderError::TrackingPushFailed {
+            failed,
+            total: results.len(),
+            result_file: result_file.display().to_string(),
+        });
+    }
 
     Ok(())
 }
 
+/// The name of a file given on the command line
+fn file_name(path: &Path) -> Result<&str> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| crate::error::WunderError::InvalidFileName(path.display().to_string()))
+}
+
 async fn push_invoice<P>(config: &crate::config::Config, tracking_file: P) -> Result<()> 
 where
     P: AsRef<Path>
 {
     debug!("Pushing invoice from {}", tracking_file.as_ref().display());
-    let file_name = tracking_file.as_ref().file_name().unwrap().to_str().unwrap();
-    let extension = tracking_file.as_ref().extension().unwrap().to_str().unwrap();
-    let order_id = file_name.split('_').next().unwrap();
-    let file = tokio::fs::File::open(&tracking_file).await?;
-    let stream = FramedRead::new(file, BytesCodec::new());
-    let file_body = Body::wrap_stream(stream);
+    let file_name = file_name(tracking_file.as_ref())?;
+    let extension = tracking_file.as_ref().extension().and_then(|e| e.to_str()).unwrap_or_default();
+    let order_id = file_name.split('_').next().unwrap_or_default();
 
     let upload_file_name = format!("Invoice-{}.{}", order_id, extension);
 
@@ -179,34 +972,19 @@ where
             }
         ],
     };
-    let json = serde_json::to_string(&document_info)?;
-    let json_bytes = json.as_bytes().to_vec();
-
     let client = reqwest::Client::new();
-    let response = client.post(format!("{}/api/orders/{}/documents", &config.base_url, order_id))
+    let request = client.post(format!("{}/api/orders/{}/documents", &config.base_url, order_id))
         .header("Authorization", &config.api_key)
-        .header("User-Agent", USER_AGENT)
-        .multipart(
-            // why does it have to be multipart? :(
-            reqwest::multipart::Form::new()
-                .part(
-                    "files", 
-                    reqwest::multipart::Part::stream(file_body)
-                        .file_name(upload_file_name)
-                )
-                // is this correct? find out on the next episode
-                .part(
-                    "order_documents",
-                    reqwest::multipart::Part::bytes(json_bytes)
-                        .mime_str("application/json")?
-                )
-
-        )
-        .send()
-        .await?
-        .error_for_status()?
-        .json::<models::invoices::OrderDocumentResponse>()
-        .await?;
+        .header("User-Agent", USER_AGENT);
+    // why does it have to be multipart? :(
+    let parts = vec![
+        UploadPart::file("files", tracking_file.as_ref(), upload_file_name),
+        UploadPart::json("order_documents", &document_info)?,
+    ];
+    let response = match send_upload(config, request, parts).await? {
+        Some(response) => response.json::<models::invoices::OrderDocumentResponse>().await?,
+        None => return Ok(()),
+    };
     
     // ugly
     if let Some(error_count) = response.errors_count {
@@ -216,48 +994,1079 @@ where
     }
     info!("Pushed invoice for order {}", &order_id);
 
-    todo!()
+    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = CliArgs::parse();
-    initialize_logging()?;
-    let config = config::Config::load()?;
+async fn list_incidents(config: &crate::config::Config) -> Result<()> {
+    let client = reqwest::Client::new();
+    let reasons = fetch_reasons(config, &client, "INCIDENT_OPEN").await?;
+    let orders = fetch_orders(config, &client, &[("has_incident", "true".to_string())]).await?;
 
-    let result = match args.command {
-        CliSubcommand::PullOrders => {
-            info!("Pulling orders");
-            pull_orders(&config).await
-        },
-        CliSubcommand::PushTrackingInfo { tracking_file } => {
-            info!("Pushing tracking info");
-            let tracking_path = PathBuf::from(&tracking_file);
-            if !tracking_path.exists() {
-                return Err(crate::error::WunderError::TrackingFileNotFound(tracking_file));
-            }
+    let mut incident_count = 0;
+    for order in &orders {
+        let order_incidents = OrderIncidents::from_order(order, &reasons);
+        incident_count += order_incidents.incidents.len();
+        for incident in &order_incidents.incidents {
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}",
+                order_incidents.order_id,
+                incident.order_line_id,
+                incident.offer_sku,
+                incident.quantity,
+                incident.reason_code.as_deref().unwrap_or("-"),
+                incident.reason_label.as_deref().unwrap_or("-"),
+            );
+        }
+    }
+    info!("Found {} incidents on {} orders", incident_count, orders.len());
 
-            push_tracking_info(&config, tracking_path).await
-        },
-        CliSubcommand::PushInvoice { invoice_file } => {
-            info!("Pushing invoice");
-            let invoice_path = PathBuf::from(&invoice_file);
-            if !invoice_path.exists() {
-                return Err(crate::error::WunderError::InvoiceFileNotFound(invoice_file));
-            }
+    Ok(())
+}
 
-            let file_ext = invoice_path.extension().unwrap().to_str().unwrap().to_lowercase();
-            if !DOCUMENT_FORMATS.contains(&file_ext.as_str()) {
-                return Err(crate::error::WunderError::DocumentFormatNotSupported(file_ext.to_string()));
-            }
+async fn pull_incidents(config: &crate::config::Config) -> Result<()> {
+    let incident_path = PathBuf::from(&config.incident_settings.incident_path);
+    if !incident_path.exists() {
+        tokio::fs::create_dir_all(&incident_path).await?;
+    }
+
+    let client = reqwest::Client::new();
+    let reasons = fetch_reasons(config, &client, "INCIDENT_OPEN").await?;
+    let orders = fetch_orders(config, &client, &[("has_incident", "true".to_string())]).await?;
 
-            push_invoice(&config, invoice_path).await
+    for order in &orders {
+        let order_incidents = OrderIncidents::from_order(order, &reasons);
+        if order_incidents.incidents.is_empty() {
+            continue;
         }
-    };
-    if let Err(e) = result {
-        tracing::error!("{}", e);
-        return Err(e);
+
+        let incident_file = incident_path.join(format!("{}-{}-Incidents.xml", chrono::Local::now().format("%Y%m%d-%H%M%S"), order.order_id));
+        let mut file = File::create(&incident_file)?;
+        let incident_xml = to_xml("OrderIncidents", &order_incidents)?;
+        file.write_all(incident_xml.as_bytes())?;
+        info!("Wrote {} incidents of order {} to {}", order_incidents.incidents.len(), order.order_id, incident_file.display());
+        config.report.count(Counter::Exported, order_incidents.incidents.len());
     }
 
     Ok(())
 }
+
+/// Opens or closes an incident on an order line.
+async fn update_incident(config: &crate::config::Config, action: IncidentAction, order_id: &str, order_line_id: &str, reason_code: &str) -> Result<()> {
+    let client = reqwest::Client::new();
+    let reason_type = action.reason_type();
+    let reasons = fetch_reasons(config, &client, reason_type).await?;
+    if !reasons.iter().any(|r| r.code == reason_code) {
+        return Err(crate::error::WunderError::UnknownReasonCode(reason_code.to_string(), reason_type.to_string()));
+    }
+
+    let request = client.put(format!("{}/api/orders/{}/lines/{}/{}", &config.base_url, order_id, order_line_id, action.endpoint()))
+        .header("Authorization", &config.api_key)
+        .header("User-Agent", USER_AGENT)
+        .json(&IncidentRequest { reason_code: reason_code.to_string() });
+    if send_mutation(config, request).await?.is_none() {
+        info!("Dry run, would have updated incident ({:?}) on order line {} of order {} with reason {}", action, order_line_id, order_id, reason_code);
+        return Ok(());
+    }
+    info!("Updated incident ({:?}) on order line {} of order {} with reason {}", action, order_line_id, order_id, reason_code);
+    config.report.count(Counter::Updated, 1);
+
+    Ok(())
+}
+
+fn read_stock_file(stock_file: &Path) -> Result<Vec<StockRecord>> {
+    let extension = stock_file.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    match extension.as_str() {
+        "csv" => csv::Reader::from_path(stock_file)?
+            .into_deserialize()
+            .collect::<std::result::Result<Vec<StockRecord>, _>>()
+            .map_err(Into::into),
+        "xml" => {
+            let buf_reader = std::io::BufReader::new(File::open(stock_file)?);
+            let stock_update: XmlStockUpdate = quick_xml::de::from_reader(buf_reader)?;
+            Ok(stock_update.offers)
+        },
+        _ => Err(crate::error::WunderError::StockFormatNotSupported(extension)),
+    }
+}
+
+async fn download_report(config: &crate::config::Config, client: &reqwest::Client, url: String, report_file: &Path) -> Result<()> {
+    let response = client.get(url)
+        .header("Authorization", &config.api_key)
+        .header("User-Agent", USER_AGENT)
+        .send_traced(config)
+        .await?;
+    let report = check_status(response).await?
+        .bytes()
+        .await?;
+    tokio::fs::write(report_file, &report).await?;
+    info!("Wrote report to {}", report_file.display());
+
+    Ok(())
+}
+
+/// Uploads the file of an import to the `offers` or `products` imports, `None` is returned in dry-run mode.
+async fn upload_import(config: &crate::config::Config, client: &reqwest::Client, kind: &str, parts: Vec<UploadPart>) -> Result<Option<ImportResponse>> {
+    let request = client.post(format!("{}/api/{}/imports", &config.base_url, kind))
+        .header("Authorization", &config.api_key)
+        .header("User-Agent", USER_AGENT);
+    match send_upload(config, request, parts).await? {
+        Some(response) => Ok(Some(response.json::<ImportResponse>().await?)),
+        None => Ok(None),
+    }
+}
+
+/// Checks the status of an import every `poll_interval` seconds until it is finished, at most `max_polls` times.
+async fn poll_import<S: ImportStatus>(config: &crate::config::Config, client: &reqwest::Client, kind: &str, import_id: u64, poll_interval: u64, max_polls: u32) -> Result<S> {
+    let mut polls = 0;
+    loop {
+        let response = client.get(format!("{}/api/{}/imports/{}", &config.base_url, kind, import_id))
+            .header("Authorization", &config.api_key)
+            .header("User-Agent", USER_AGENT)
+            .send_traced(config)
+            .await?;
+        let status = check_status(response).await?
+            .json::<S>()
+            .await?;
+        debug!("Import {} of {} is {}", import_id, kind, status.status());
+        polls += 1;
+        if status.is_finished() {
+            return Ok(status);
+        }
+        if polls >= max_polls {
+            return Err(crate::error::WunderError::ImportTimedOut { import_id, polls, status: status.status().to_string() });
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(poll_interval)).await;
+    }
+}
+
+async fn push_offers<P>(config: &crate::config::Config, stock_file: P) -> Result<()>
+where
+    P: AsRef<Path>
+{
+    let settings = &config.offer_settings;
+    debug!("Pushing offers from {}", stock_file.as_ref().display());
+    let records = read_stock_file(stock_file.as_ref())?;
+    let line_count = records.len();
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(b';')
+        .from_writer(vec![]);
+    for record in records {
+        writer.serialize(OfferImportLine::from_stock(record, settings))?;
+    }
+    let import_file = writer.into_inner().map_err(|e| e.into_error())?;
+
+    let client = reqwest::Client::new();
+    let parts = vec![
+        UploadPart::bytes("file", "offers.csv", import_file),
+        UploadPart::text("import_mode", &settings.import_mode),
+    ];
+    let import = match upload_import(config, &client, "offers", parts).await? {
+        Some(import) => import,
+        None => return Ok(()),
+    };
+    info!("Uploaded {} offers, import id {}", line_count, import.import_id);
+
+    let status: OfferImportStatus = poll_import(config, &client, "offers", import.import_id, settings.poll_interval, settings.max_polls).await?;
+
+    if status.status == "FAILED" {
+        return Err(crate::error::WunderError::ImportFailed(import.import_id, status.reason_status.unwrap_or_default()));
+    }
+    info!(
+        "Offer import {} complete: {} lines read, {} in success, {} in error ({} inserted, {} updated, {} deleted)",
+        import.import_id,
+        status.lines_read.unwrap_or_default(),
+        status.lines_in_success.unwrap_or_default(),
+        status.lines_in_error.unwrap_or_default(),
+        status.offer_inserted.unwrap_or_default(),
+        status.offer_updated.unwrap_or_default(),
+        status.offer_deleted.unwrap_or_default(),
+    );
+    config.report.count(Counter::Imported, status.lines_in_success.unwrap_or_default() as usize);
+    config.report.count(Counter::Rejected, status.lines_in_error.unwrap_or_default() as usize);
+
+    if status.has_error_report.unwrap_or_default() || status.lines_in_error.unwrap_or_default() > 0 {
+        let report_path = PathBuf::from(&settings.report_path);
+        if !report_path.exists() {
+            tokio::fs::create_dir_all(&report_path).await?;
+        }
+        let report_file = report_path.join(format!("{}-{}-OffersErrorReport.csv", chrono::Local::now().format("%Y%m%d-%H%M%S"), import.import_id));
+        download_report(config, &client, format!("{}/api/offers/imports/{}/error_report", &config.base_url, import.import_id), &report_file).await?;
+        warn!("{} offers were rejected, see {}", status.lines_in_error.unwrap_or_default(), report_file.display());
+        // a partly rejected import must not look like a clean one to whoever runs the command
+        if status.lines_in_error.unwrap_or_default() > 0 {
+            return Err(crate::error::WunderError::OffersRejected {
+                import_id: import.import_id,
+                read: status.lines_read.unwrap_or_default(),
+                rejected: status.lines_in_error.unwrap_or_default(),
+                report_file: report_file.display().to_string(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+async fn push_products<P>(config: &crate::config::Config, product_file: P) -> Result<()>
+where
+    P: AsRef<Path>
+{
+    let settings = &config.product_settings;
+    debug!("Pushing products from {}", product_file.as_ref().display());
+    let file_name = file_name(product_file.as_ref())?.to_string();
+    let parts = vec![UploadPart::file("file", product_file.as_ref(), file_name)];
+
+    let client = reqwest::Client::new();
+    let import = match upload_import(config, &client, "products", parts).await? {
+        Some(import) => import,
+        None => return Ok(()),
+    };
+    info!("Uploaded product file, import id {}", import.import_id);
+
+    let status: ProductImportStatus = poll_import(config, &client, "products", import.import_id, settings.poll_interval, settings.max_polls).await?;
+
+    let report_path = PathBuf::from(&settings.report_path);
+    let reports = status.available_reports();
+    if !reports.is_empty() && !report_path.exists() {
+        tokio::fs::create_dir_all(&report_path).await?;
+    }
+    for (endpoint, suffix) in reports {
+        let report_file = report_path.join(format!("{}-{}-Products{}.csv", chrono::Local::now().format("%Y%m%d-%H%M%S"), import.import_id, suffix));
+        download_report(config, &client, format!("{}/api/products/imports/{}/{}", &config.base_url, import.import_id, endpoint), &report_file).await?;
+    }
+
+    if status.import_status != "COMPLETE" {
+        return Err(crate::error::WunderError::ImportFailed(import.import_id, status.reason_status.unwrap_or(status.import_status)));
+    }
+
+    let read = status.transform_lines_read.unwrap_or_default();
+    let rejected = status.transform_lines_in_error.unwrap_or_default();
+    info!(
+        "Product import {} complete: {} lines read, {} in success, {} with warnings, {} in error",
+        import.import_id,
+        read,
+        status.transform_lines_in_success.unwrap_or_default(),
+        status.transform_lines_with_warning.unwrap_or_default(),
+        rejected,
+    );
+    config.report.count(Counter::Imported, status.transform_lines_in_success.unwrap_or_default() as usize);
+    config.report.count(Counter::Rejected, rejected as usize);
+    if rejected > 0 || status.has_error_report.unwrap_or_default() {
+        return Err(crate::error::WunderError::ProductsRejected {
+            import_id: import.import_id,
+            read,
+            rejected,
+            report_path: report_path.display().to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+async fn pull_documents(config: &crate::config::Config, order_ids: Vec<String>, type_code: Option<String>, list_only: bool) -> Result<()> {
+    let order_path = PathBuf::from(&config.pull_order_settings.order_path);
+    if !list_only && !order_path.exists() {
+        tokio::fs::create_dir_all(&order_path).await?;
+    }
+
+    let client = reqwest::Client::new();
+    let order_ids = if order_ids.is_empty() {
+        fetch_orders(config, &client, &[("order_state_codes", config.pull_order_settings.order_state_codes())])
+            .await?
+            .into_iter()
+            .map(|order| order.order_id)
+            .collect()
+    } else {
+        order_ids
+    };
+
+    // the documents endpoint accepts at most 100 orders per call
+    for chunk in order_ids.chunks(100) {
+        let response = client.get(format!("{}/api/orders/documents", &config.base_url))
+            .header("Authorization", &config.api_key)
+            .header("User-Agent", USER_AGENT)
+            .query(&[("order_ids", chunk.join(","))])
+            .send_traced(config)
+            .await?;
+        let documents = check_status(response).await?
+            .json::<OrderDocumentListResponse>()
+            .await?;
+        debug!("Fetched {} documents", documents.total_count);
+
+        for document in documents.order_documents {
+            if matches!(&type_code, Some(t) if t != &document.type_code) {
+                continue;
+            }
+            if list_only {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}\t{}",
+                    document.order_id,
+                    document.id,
+                    document.type_code,
+                    document.file_name,
+                    document.file_size.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string()),
+                    document.date_uploaded,
+                );
+                continue;
+            }
+
+            // the names come from the marketplace, they must not lead out of the order path
+            let names = (safe_file_name(&document.order_id), safe_file_name(&format!("{}_{}", document.type_code, document.file_name)));
+            let (order_id, suffix) = match names {
+                (Some(order_id), Some(suffix)) => (order_id, suffix),
+                _ => {
+                    warn!("Skipping document {} of order {}, its name can't be used for a file: {}", document.id, document.order_id, document.file_name);
+                    continue;
+                },
+            };
+            let document_file = order_path.join(order_file_name(&order_id, &suffix));
+            let response = client.get(format!("{}/api/orders/documents/download", &config.base_url))
+                .header("Authorization", &config.api_key)
+                .header("User-Agent", USER_AGENT)
+                .query(&[("document_ids", document.id.to_string())])
+                .send_traced(config)
+                .await?;
+            let mut response = check_status(response).await?;
+            let mut file = tokio::fs::File::create(&document_file).await?;
+            while let Some(chunk) = response.chunk().await? {
+                file.write_all(&chunk).await?;
+            }
+            file.flush().await?;
+            info!("Wrote document {} of order {} to {}", document.id, document.order_id, document_file.display());
+            config.report.count(Counter::Downloaded, 1);
+        }
+    }
+
+    Ok(())
+}
+
+fn write_records<T, P>(path: P, records: &[T], format: ExportFormat) -> Result<()>
+where
+    T: serde::Serialize,
+    P: AsRef<Path>
+{
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_path(path)?;
+            for record in records {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        },
+        ExportFormat::Json => {
+            let file = File::create(path)?;
+            serde_json::to_writer_pretty(file, records)?;
+        },
+    }
+
+    Ok(())
+}
+
+async fn pull_transactions(config: &crate::config::Config, from: Option<String>, to: Option<String>, format: ExportFormat, resume: bool) -> Result<()> {
+    let settings = &config.transaction_settings;
+    let transaction_path = PathBuf::from(&settings.transaction_path);
+    if !transaction_path.exists() {
+        tokio::fs::create_dir_all(&transaction_path).await?;
+    }
+
+    let state_file = transaction_path.join("transactions.state.json");
+    let mut state = if resume && state_file.exists() {
+        serde_json::from_reader(File::open(&state_file)?)?
+    } else {
+        TransactionState::default()
+    };
+    // the lower bounds are inclusive, skip what the previous runs already exported
+    let skip_exported = resume && from.is_none();
+    let client = reqwest::Client::new();
+
+    // transaction logs use token based pagination
+    let transactions_from = from.clone().or_else(|| state.last_transaction_date.clone());
+    debug!("Fetching transactions from {:?} to {:?}", transactions_from, to);
+    let mut transactions: Vec<TransactionRecord> = vec![];
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut query = vec![("limit", settings.page_size.to_string())];
+        if let Some(date) = &transactions_from {
+            query.push(("date_created_from", date.clone()));
+        }
+        if let Some(date) = &to {
+            query.push(("date_created_to", date.clone()));
+        }
+        if let Some(token) = &page_token {
+            query.push(("page_token", token.clone()));
+        }
+        let response = client.get(format!("{}/api/sellerpayment/transactions_logs", &config.base_url))
+            .header("Authorization", &config.api_key)
+            .header("User-Agent", USER_AGENT)
+            .query(&query)
+            .send_traced(config)
+            .await?;
+        let mut response = check_status(response).await?
+            .json::<TransactionLogResponse>()
+            .await?;
+        if config.unknown_fields == UnknownFieldsMode::Strip {
+            response.data.strip_unknown_fields();
+        }
+        transactions.extend(response.data.into_iter().map(Into::into));
+        page_token = response.next_page_token;
+        if page_token.is_none() {
+            break;
+        }
+    }
+    if skip_exported {
+        transactions.retain(|t| !state.transaction_exported(t));
+    }
+
+    // billing cycles use offset based pagination
+    let cycles_from = from.or_else(|| state.last_billing_cycle_date.clone());
+    let mut billing_cycles: Vec<BillingCycleRecord> = vec![];
+    let mut offset = 0;
+    loop {
+        let mut query = vec![("max", settings.page_size.to_string()), ("offset", offset.to_string())];
+        if let Some(date) = &cycles_from {
+            query.push(("start_date", date.clone()));
+        }
+        if let Some(date) = &to {
+            query.push(("end_date", date.clone()));
+        }
+        let response = client.get(format!("{}/api/invoices", &config.base_url))
+            .header("Authorization", &config.api_key)
+            .header("User-Agent", USER_AGENT)
+            .query(&query)
+            .send_traced(config)
+            .await?;
+        let mut response = check_status(response).await?
+            .json::<BillingCycleResponse>()
+            .await?;
+        if config.unknown_fields == UnknownFieldsMode::Strip {
+            response.invoices.strip_unknown_fields();
+        }
+        let page_len = response.invoices.len() as u32;
+        billing_cycles.extend(response.invoices.into_iter().map(Into::into));
+        offset += page_len;
+        if page_len == 0 || offset >= response.total_count {
+            break;
+        }
+    }
+    if skip_exported {
+        billing_cycles.retain(|c| !state.billing_cycle_exported(c));
+    }
+
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    if !transactions.is_empty() {
+        let transaction_file = transaction_path.join(format!("{}-Transactions.{}", timestamp, format.extension()));
+        write_records(&transaction_file, &transactions, format)?;
+        info!("Wrote {} transactions to {}", transactions.len(), transaction_file.display());
+    }
+    if !billing_cycles.is_empty() {
+        let billing_cycle_file = transaction_path.join(format!("{}-BillingCycles.{}", timestamp, format.extension()));
+        write_records(&billing_cycle_file, &billing_cycles, format)?;
+        info!("Wrote {} billing cycles to {}", billing_cycles.len(), billing_cycle_file.display());
+    }
+    config.report.count(Counter::Exported, transactions.len() + billing_cycles.len());
+
+    // only resumed runs move the saved position, ad-hoc exports leave it alone
+    if resume {
+        state.advance_transactions(&transactions);
+        state.advance_billing_cycles(&billing_cycles);
+        serde_json::to_writer_pretty(File::create(&state_file)?, &state)?;
+    }
+
+    Ok(())
+}
+
+async fn reconcile<P>(config: &crate::config::Config, erp_file: P, format: ExportFormat) -> Result<()>
+where
+    P: AsRef<Path>
+{
+    let settings = &config.reconcile_settings;
+    debug!("Reconciling invoiced amounts from {}", erp_file.as_ref().display());
+    let erp_records = csv::Reader::from_path(erp_file.as_ref())?
+        .into_deserialize()
+        .collect::<std::result::Result<Vec<ErpInvoiceRecord>, _>>()?;
+
+    let client = reqwest::Client::new();
+    let mut orders = std::collections::HashMap::new();
+    let order_ids = erp_records.iter().map(|r| r.order_id.clone()).collect::<Vec<_>>();
+    for chunk in order_ids.chunks(100) {
+        for order in fetch_orders(config, &client, &[("order_ids", chunk.join(","))]).await? {
+            orders.insert(order.order_id.clone(), order);
+        }
+    }
+
+    let mut report = erp_records.iter()
+        .map(|erp| ReconciliationRecord::compare(erp, orders.get(&erp.order_id), settings.tolerance))
+        .collect::<Vec<_>>();
+
+    // the marketplace orders created in the period of the ERP export should all be in it
+    let created_dates = orders.values()
+        .filter_map(|order| chrono::DateTime::parse_from_rfc3339(&order.created_date).ok().map(|date| (date, &order.created_date)))
+        .collect::<Vec<_>>();
+    let period = (created_dates.iter().min(), created_dates.iter().max());
+    if let (Some((_, start_date)), Some((_, end_date))) = period {
+        let query = [
+            ("order_state_codes", settings.order_state_codes.join(",")),
+            ("start_date", start_date.to_string()),
+            ("end_date", end_date.to_string()),
+        ];
+        for order in fetch_orders(config, &client, &query).await? {
+            if !orders.contains_key(&order.order_id) {
+                report.push(ReconciliationRecord::missing_in_erp(&order));
+            }
+        }
+    }
+    let discrepancies = report.iter().filter(|r| r.status != ReconciliationStatus::Match).count();
+
+    let report_path = PathBuf::from(&settings.report_path);
+    if !report_path.exists() {
+        tokio::fs::create_dir_all(&report_path).await?;
+    }
+    let report_file = report_path.join(format!("{}-Reconciliation.{}", chrono::Local::now().format("%Y%m%d-%H%M%S"), format.extension()));
+    write_records(&report_file, &report, format)?;
+    info!("Reconciled {} orders, {} discrepancies, report written to {}", report.len(), discrepancies, report_file.display());
+    config.report.count(Counter::Exported, report.len());
+    config.report.count(Counter::Mismatched, discrepancies);
+
+    if discrepancies > 0 {
+        return Err(crate::error::WunderError::ReconciliationDiscrepancies {
+            count: discrepancies,
+            report_file: report_file.display().to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+async fn fetch_returns(config: &crate::config::Config, client: &reqwest::Client) -> Result<Vec<Return>> {
+    let mut returns = vec![];
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut query = vec![];
+        if !config.return_settings.return_state_codes.is_empty() {
+            query.push(("states", config.return_settings.return_state_codes()));
+        }
+        if let Some(token) = &page_token {
+            query.push(("page_token", token.clone()));
+        }
+        let response = client.get(format!("{}/api/returns", &config.base_url))
+            .header("Authorization", &config.api_key)
+            .header("User-Agent", USER_AGENT)
+            .query(&query)
+            .send_traced(config)
+            .await?;
+        let mut response = check_status(response).await?
+            .json::<ReturnResponse>()
+            .await?;
+        debug!("Fetched {} returns", response.data.len());
+        config.report.count(Counter::Fetched, response.data.len());
+        if config.unknown_fields == UnknownFieldsMode::Strip {
+            response.data.strip_unknown_fields();
+        }
+        returns.extend(response.data);
+        page_token = response.next_page_token;
+        if page_token.is_none() {
+            break;
+        }
+    }
+
+    Ok(returns)
+}
+
+async fn list_returns(config: &crate::config::Config) -> Result<()> {
+    let client = reqwest::Client::new();
+    let returns = fetch_returns(config, &client).await?;
+    for ret in &returns {
+        for line in &ret.return_lines {
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                ret.id,
+                ret.order_id,
+                ret.state,
+                line.id,
+                line.offer_sku.as_deref().unwrap_or("-"),
+                line.quantity,
+                line.reason_code.as_deref().or(ret.reason_code.as_deref()).unwrap_or("-"),
+            );
+        }
+    }
+    info!("Found {} returns", returns.len());
+
+    Ok(())
+}
+
+async fn pull_returns(config: &crate::config::Config) -> Result<()> {
+    let return_path = PathBuf::from(&config.return_settings.return_path);
+    if !return_path.exists() {
+        tokio::fs::create_dir_all(&return_path).await?;
+    }
+
+    let client = reqwest::Client::new();
+    for ret in fetch_returns(config, &client).await? {
+        let return_file = return_path.join(order_file_name(&ret.order_id, &format!("Return_{}.xml", ret.id)));
+        let mut file = File::create(&return_file)?;
+        let return_xml = to_xml("Return", &ret)?;
+        file.write_all(return_xml.as_bytes())?;
+        info!("Wrote return {} to {}", ret.id, return_file.display());
+        config.report.count(Counter::Exported, 1);
+    }
+
+    Ok(())
+}
+
+async fn push_return_updates<P>(config: &crate::config::Config, return_file: P) -> Result<()>
+where
+    P: AsRef<Path>
+{
+    debug!("Pushing return updates from {}", return_file.as_ref().display());
+    let records = csv::Reader::from_path(return_file.as_ref())?
+        .into_deserialize()
+        .collect::<std::result::Result<Vec<ReturnUpdateRecord>, _>>()?;
+
+    // one call per return and status, keeping the order of the file
+    let client = reqwest::Client::new();
+    for update in ReturnUpdate::group(records) {
+        let line_count = update.request.return_lines.len();
+        let request = client.put(format!("{}/api/returns/{}/{}", &config.base_url, update.return_id, update.status.action()))
+            .header("Authorization", &config.api_key)
+            .header("User-Agent", USER_AGENT)
+            .json(&update.request);
+        if send_mutation(config, request).await?.is_none() {
+            info!("Dry run, would have marked {} lines of return {} as {:?}", line_count, update.return_id, update.status);
+            continue;
+        }
+        info!("Marked {} lines of return {} as {:?}", line_count, update.return_id, update.status);
+        config.report.count(Counter::Updated, line_count);
+    }
+
+    Ok(())
+}
+
+async fn set_additional_fields<P>(config: &crate::config::Config, field_file: P) -> Result<()>
+where
+    P: AsRef<Path>
+{
+    debug!("Setting additional fields from {}", field_file.as_ref().display());
+    let records = csv::Reader::from_path(field_file.as_ref())?
+        .into_deserialize()
+        .collect::<std::result::Result<Vec<AdditionalFieldRecord>, _>>()?;
+
+    let client = reqwest::Client::new();
+    let definitions = fetch_additional_field_definitions(config, &client).await?;
+
+    // every line is checked before anything is sent, one call per order keeping the order of the file
+    let mut requests: Vec<(String, AdditionalFieldsRequest)> = vec![];
+    let mut invalid = vec![];
+    for (index, record) in records.into_iter().enumerate() {
+        let entity = if record.order_line_id.is_some() { "ORDER_LINE" } else { "ORDER" };
+        let definition = definitions.find(entity, &record.code);
+        let field = match (record.field_type, definition) {
+            (_, None) => Err(format!("field {} is not defined on the marketplace for {}", record.code, entity)),
+            (field_type, Some(definition)) => AdditionalField::parse(field_type.unwrap_or(definition.field_type), record.code, &record.value)
+                .and_then(|field| field.check(definition).map(|_| field)),
+        };
+        let field = match field {
+            Ok(field) => field,
+            Err(e) => {
+                // the header is line 1
+                invalid.push(format!("line {} (order {}): {}", index + 2, record.order_id, e));
+                continue;
+            },
+        };
+        match requests.iter_mut().find(|(order_id, _)| order_id == &record.order_id) {
+            Some((_, request)) => request.add(record.order_line_id, field),
+            None => {
+                let mut request = AdditionalFieldsRequest::default();
+                request.add(record.order_line_id, field);
+                requests.push((record.order_id, request));
+            },
+        }
+    }
+    if !invalid.is_empty() {
+        return Err(crate::error::WunderError::InvalidAdditionalFields {
+            count: invalid.len(),
+            details: invalid.join("\n"),
+        });
+    }
+
+    for (order_id, request) in requests {
+        let field_count = request.field_count();
+        let request = client.put(format!("{}/api/orders/{}/additional_fields", &config.base_url, order_id))
+            .header("Authorization", &config.api_key)
+            .header("User-Agent", USER_AGENT)
+            .json(&request);
+        if send_mutation(config, request).await?.is_none() {
+            info!("Dry run, would have updated {} additional fields of order {}", field_count, order_id);
+            continue;
+        }
+        info!("Updated {} additional fields of order {}", field_count, order_id);
+        config.report.count(Counter::Updated, 1);
+    }
+
+    Ok(())
+}
+
+async fn notify_deadlines(config: &crate::config::Config, client: &reqwest::Client, notification: &DeadlineNotification<'_>) -> Result<()> {
+    let settings = &config.deadline_settings;
+    if let Some(webhook_url) = &settings.webhook_url {
+        let request = client.post(webhook_url)
+            .header("User-Agent", USER_AGENT)
+            .json(notification);
+        send_mutation(config, request).await?;
+        debug!("Posted deadline notification to {}", webhook_url);
+    }
+
+    // the mail is written as a file for the local mail pickup to send
+    if let Some(mail_path) = &settings.mail_path {
+        let mail_path = PathBuf::from(mail_path);
+        if !mail_path.exists() {
+            tokio::fs::create_dir_all(&mail_path).await?;
+        }
+        let mut body = String::new();
+        for alert in notification.alerts {
+            let status = if alert.overdue { "OVERDUE" } else { "DUE SOON" };
+            body.push_str(&format!("{}\t{}\t{}\t{} ({})\r\n", status, alert.order_id, alert.commercial_id, alert.shipping_deadline, alert.time_left()));
+        }
+        let mail = format!(
+            "From: {}\r\nTo: {}\r\nDate: {}\r\nSubject: [wunder] {} orders overdue, {} due soon\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}",
+            settings.mail_from,
+            settings.mail_to.join(", "),
+            chrono::Local::now().to_rfc2822(),
+            notification.overdue,
+            notification.due_soon,
+            body,
+        );
+        let mail_file = mail_path.join(format!("{}-ShippingDeadlines.eml", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+        tokio::fs::write(&mail_file, mail).await?;
+        debug!("Wrote deadline notification to {}", mail_file.display());
+    }
+
+    Ok(())
+}
+
+/// Reports the orders at risk, notifying those not in `notified` yet.
+async fn check_deadlines(config: &crate::config::Config, window: Option<i64>, notified: &mut NotifiedAlerts) -> Result<()> {
+    let window_hours = window.unwrap_or(config.deadline_settings.window_hours);
+    let client = reqwest::Client::new();
+    let orders = fetch_orders(config, &client, &[("order_state_codes", "SHIPPING".to_string())]).await?;
+
+    let now = chrono::Utc::now();
+    let mut alerts = vec![];
+    for order in &orders {
+        match DeadlineAlert::check(order, now, window_hours) {
+            Ok(alert) => alerts.extend(alert),
+            Err(e) => {
+                warn!("Could not parse shipping deadline {} of order {}, it can't be checked: {}", order.shipping_deadline.as_deref().unwrap_or_default(), order.order_id, e);
+                config.report.count(Counter::Unreadable, 1);
+            },
+        }
+    }
+    alerts.sort_by_key(|alert| alert.minutes_left);
+    for alert in &alerts {
+        println!(
+            "{}\t{}\t{}\t{}",
+            if alert.overdue { "OVERDUE" } else { "DUE_SOON" },
+            alert.order_id,
+            alert.shipping_deadline,
+            alert.time_left(),
+        );
+    }
+
+    let overdue = alerts.iter().filter(|alert| alert.overdue).count();
+    let due_soon = alerts.len() - overdue;
+    info!("Checked {} orders in SHIPPING: {} overdue, {} due within {}h", orders.len(), overdue, due_soon, window_hours);
+    if alerts.is_empty() {
+        return Ok(());
+    }
+
+    let fresh = notified.fresh(&alerts);
+    if fresh.is_empty() {
+        debug!("All orders at risk were already notified");
+    } else {
+        let fresh_overdue = fresh.iter().filter(|alert| alert.overdue).count();
+        let notification = DeadlineNotification { overdue: fresh_overdue, due_soon: fresh.len() - fresh_overdue, alerts: &fresh };
+        notify_deadlines(config, &client, &notification).await?;
+    }
+    Err(crate::error::WunderError::ShippingDeadlinesAtRisk { overdue, due_soon })
+}
+
+#[tokio::main]
+async fn main() -> std::process::ExitCode {
+    let matches = CliArgs::command().get_matches();
+    let args = CliArgs::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let command = matches.subcommand_name().unwrap_or_default();
+    match run(args, command).await {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            let status = e.exit_status();
+            // errors are reported as diagnostics, with the offending source when there is one
+            eprintln!("Error: {:?}", miette::Report::new(e));
+            status.into()
+        },
+    }
+}
+
+/// The path of an input file, `not_found` when it doesn't exist
+fn existing_file(file: String, not_found: fn(String) -> crate::error::WunderError) -> Result<PathBuf> {
+    let path = PathBuf::from(&file);
+    if !path.exists() {
+        return Err(not_found(file));
+    }
+    Ok(path)
+}
+
+/// Writes the report of the run to `path` when one was given, failing to do so doesn't fail the run
+fn write_report(run_report: &report::RunReport, path: &Option<String>, command: &str, result: &Result<()>) {
+    if let Some(path) = path {
+        if let Err(e) = run_report.write(path, command, result) {
+            warn!("Could not write the run report to {}: {}", path, e);
+        }
+    }
+}
+
+async fn run(args: CliArgs, command: &str) -> Result<()> {
+    // the mock server does not talk to the marketplace, it needs no config
+    if let CliSubcommand::MockServer { fixtures, addr, page_size, fail_every, fail_status, fail_path } = args.command {
+        logging::initialize(&config::LogSettings::default(), args.verbose, args.quiet)?;
+        return mock::run(mock::MockSettings { addr, fixtures, page_size, fail_every, fail_status, fail_path }).await;
+    }
+    let mut config = match config::Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            logging::initialize(&config::LogSettings::default(), args.verbose, args.quiet)?;
+            // a broken config is a failed run all the same, monitoring must see it
+            let result = Err(e);
+            write_report(&report::RunReport::default(), &args.report, command, &result);
+            return result;
+        },
+    };
+    config.log_settings.trace_http |= args.trace_http;
+    logging::initialize(&config.log_settings, args.verbose, args.quiet)?;
+    if args.dry_run {
+        config.enable_dry_run();
+        info!("Dry run, exports are written to {}", config.dry_run_path);
+    }
+
+    let result = match args.command {
+        CliSubcommand::PullOrders { order_state_codes, filters } => {
+            info!("Pulling orders");
+            let order_state_codes = if order_state_codes.is_empty() {
+                config.pull_order_settings.order_state_codes()
+            } else {
+                order_state_codes.join(",")
+            };
+            let filters = config.pull_order_settings.filters.merge(&filters);
+            pull_orders(&config, order_state_codes, filters).await
+        },
+        CliSubcommand::AcceptOrders { order_ids } => {
+            info!("Accepting orders");
+            review_orders(&config, order_ids, AcceptanceDecision::Accept).await
+        },
+        CliSubcommand::RefuseOrders { order_ids } => {
+            info!("Refusing orders");
+            review_orders(&config, order_ids, AcceptanceDecision::Refuse).await
+        },
+        CliSubcommand::GetOrder { order_ids, format, save } => {
+            info!("Getting orders");
+            get_orders(&config, order_ids, format, save).await
+        },
+        CliSubcommand::PushTrackingInfo { tracking_file } => {
+            info!("Pushing tracking info");
+            match existing_file(tracking_file, crate::error::WunderError::TrackingFileNotFound) {
+                Ok(tracking_path) => push_tracking_info(&config, tracking_path).await,
+                Err(e) => Err(e),
+            }
+        },
+        CliSubcommand::PushInvoice { invoice_file } => {
+            info!("Pushing invoice");
+            let invoice_path = existing_file(invoice_file, crate::error::WunderError::InvoiceFileNotFound).and_then(|invoice_path| {
+                let file_ext = invoice_path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+                if !DOCUMENT_FORMATS.contains(&file_ext.as_str()) {
+                    return Err(crate::error::WunderError::DocumentFormatNotSupported(file_ext));
+                }
+                Ok(invoice_path)
+            });
+            match invoice_path {
+                Ok(invoice_path) => push_invoice(&config, invoice_path).await,
+                Err(e) => Err(e),
+            }
+        },
+        CliSubcommand::ListIncidents => {
+            info!("Listing incidents");
+            list_incidents(&config).await
+        },
+        CliSubcommand::PullIncidents => {
+            info!("Pulling incidents");
+            pull_incidents(&config).await
+        },
+        CliSubcommand::OpenIncident { order_id, order_line_id, reason_code } => {
+            info!("Opening incident");
+            update_incident(&config, IncidentAction::Open, &order_id, &order_line_id, &reason_code).await
+        },
+        CliSubcommand::CloseIncident { order_id, order_line_id, reason_code } => {
+            info!("Closing incident");
+            update_incident(&config, IncidentAction::Close, &order_id, &order_line_id, &reason_code).await
+        },
+        CliSubcommand::PushOffers { stock_file } => {
+            info!("Pushing offers");
+            match existing_file(stock_file, crate::error::WunderError::StockFileNotFound) {
+                Ok(stock_path) => push_offers(&config, stock_path).await,
+                Err(e) => Err(e),
+            }
+        },
+        CliSubcommand::PushProducts { product_file } => {
+            info!("Pushing products");
+            match existing_file(product_file, crate::error::WunderError::ProductFileNotFound) {
+                Ok(product_path) => push_products(&config, product_path).await,
+                Err(e) => Err(e),
+            }
+        },
+        CliSubcommand::PullTransactions { from, to, format, resume } => {
+            info!("Pulling transactions");
+            pull_transactions(&config, from, to, format, resume).await
+        },
+        CliSubcommand::Reconcile { erp_file, format } => {
+            info!("Reconciling");
+            match existing_file(erp_file, crate::error::WunderError::ErpFileNotFound) {
+                Ok(erp_path) => reconcile(&config, erp_path, format).await,
+                Err(e) => Err(e),
+            }
+        },
+        CliSubcommand::ListReturns => {
+            info!("Listing returns");
+            list_returns(&config).await
+        },
+        CliSubcommand::PullReturns => {
+            info!("Pulling returns");
+            pull_returns(&config).await
+        },
+        CliSubcommand::PushReturnUpdates { return_file } => {
+            info!("Pushing return updates");
+            match existing_file(return_file, crate::error::WunderError::ReturnFileNotFound) {
+                Ok(return_path) => push_return_updates(&config, return_path).await,
+                Err(e) => Err(e),
+            }
+        },
+        CliSubcommand::SetAdditionalFields { field_file } => {
+            info!("Setting additional fields");
+            match existing_file(field_file, crate::error::WunderError::AdditionalFieldFileNotFound) {
+                Ok(field_path) => set_additional_fields(&config, field_path).await,
+                Err(e) => Err(e),
+            }
+        },
+        CliSubcommand::CheckDeadlines { window, interval: None } => {
+            info!("Checking shipping deadlines");
+            check_deadlines(&config, window, &mut NotifiedAlerts::default()).await
+        },
+        CliSubcommand::CheckDeadlines { window, interval: Some(interval) } => {
+            info!("Checking shipping deadlines every {}s, until interrupted", interval);
+            let mut notified = NotifiedAlerts::default();
+            loop {
+                let checked = check_deadlines(&config, window, &mut notified).await;
+                // the run doesn't end on its own, monitoring gets the report of every check
+                write_report(&config.report, &args.report, command, &checked);
+                if let Err(e) = checked {
+                    warn!("{}", e);
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(std::time::Duration::from_secs(interval)) => (),
+                    _ = tokio::signal::ctrl_c() => {
+                        info!("Stopped checking shipping deadlines");
+                        break Ok(());
+                    },
+                }
+            }
+        },
+        CliSubcommand::MockServer { .. } => unreachable!(),
+        CliSubcommand::PullDocuments { order_ids, type_code, list } => {
+            info!("Pulling documents");
+            pull_documents(&config, order_ids, type_code, list).await
+        },
+    };
+    write_report(&config.report, &args.report, command, &result);
+    if let Err(e) = result {
+        tracing::error!("{}", e);
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_from_the_marketplace_stay_in_their_folder() {
+        assert_eq!(safe_file_name("invoice.pdf").as_deref(), Some("invoice.pdf"));
+        assert_eq!(safe_file_name("../../etc/passwd").as_deref(), Some(".._.._etc_passwd"));
+        assert_eq!(safe_file_name("..\\config.toml").as_deref(), Some(".._config.toml"));
+        assert_eq!(safe_file_name(".."), None);
+        assert_eq!(safe_file_name(" "), None);
+        let document_file = Path::new("orders").join(order_file_name("1001-A", &safe_file_name("CUSTOMER_INVOICE_../x.pdf").unwrap()));
+        assert_eq!(document_file.parent(), Some(Path::new("orders")));
+    }
+
+    fn waiting_order(created_date: String) -> models::orders::Order {
+        let mut order = models::orders::fixture_order("waiting-acceptance");
+        order.created_date = created_date;
+        order
+    }
+
+    #[test]
+    fn default_decision_is_only_taken_near_a_known_deadline() {
+        // refused 24h after creation, decided 2h before
+        let settings = AcceptanceSettings::default();
+        let created_ago = |hours| waiting_order((chrono::Utc::now() - chrono::Duration::hours(hours)).to_rfc3339());
+
+        assert!(!acceptance_deadline_near(&settings, &created_ago(1)));
+        assert!(!acceptance_deadline_near(&settings, &created_ago(21)));
+        assert!(acceptance_deadline_near(&settings, &created_ago(23)));
+        assert!(acceptance_deadline_near(&settings, &created_ago(30)));
+        assert!(!acceptance_deadline_near(&settings, &waiting_order("28/11/2022 09:12".to_string())));
+    }
+
+    #[test]
+    fn order_files_are_found_by_their_exact_id() {
+        let folder = std::env::temp_dir().join(format!("wunder-order-files-{}", std::process::id()));
+        std::fs::create_dir_all(&folder).unwrap();
+        for order_id in ["1", "21-1", "1-1", "1001-A"] {
+            File::create(folder.join(order_file_name(order_id, "GetOrders_Response.xml"))).unwrap();
+        }
+        File::create(folder.join("decisions.log")).unwrap();
+
+        let found = |order_id| {
+            let files = find_order_files(&folder, order_id).unwrap();
+            files.iter().map(|file| order_file_id(&file.file_name().unwrap().to_string_lossy()).unwrap().to_string()).collect::<Vec<_>>()
+        };
+        let found = (found("1"), found("21-1"), found("21"), found("1001-A"));
+        std::fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(found, (vec!["1".to_string()], vec!["21-1".to_string()], vec![], vec!["1001-A".to_string()]));
+        assert!(find_order_files(Path::new("no-such-folder"), "1").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_spaces_out_concurrent_requests() {
+        let limiter = RateLimiter::new(Some(20.0));
+        let start = tokio::time::Instant::now();
+        let waited = futures_util::future::join_all((0..5).map(|_| async {
+            limiter.wait().await;
+            start.elapsed()
+        })).await;
+
+        // one slot every 50ms, the first one right away
+        let mut waited = waited.into_iter().map(|elapsed| elapsed.as_millis()).collect::<Vec<_>>();
+        waited.sort_unstable();
+        assert!(waited[0] < 50, "{:?}", waited);
+        for (slot, elapsed) in waited.iter().enumerate().skip(1) {
+            assert!(*elapsed >= slot as u128 * 50, "{:?}", waited);
+        }
+        assert!(waited[4] < 1000, "{:?}", waited);
+
+        let unlimited = RateLimiter::new(None);
+        let start = tokio::time::Instant::now();
+        for _ in 0..100 {
+            unlimited.wait().await;
+        }
+        assert!(start.elapsed() < std::time::Duration::from_millis(50));
+    }
+}
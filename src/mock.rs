@@ -0,0 +1,689 @@
+//! A local stand-in for the Mirakl API, used to exercise the CLI offline.
+//!
+//! Orders are loaded from JSON fixtures and kept in memory, so accepting, shipping
+//! and uploading documents change what the next requests return. Returns and
+//! transactions are made up from the shipped orders.
+
+use std::{collections::HashMap, convert::Infallible, net::SocketAddr, path::Path, sync::{Arc, Mutex}};
+
+use hyper::{Body, Method, Request, Response, StatusCode, service::{make_service_fn, service_fn}};
+use serde_json::{json, Value};
+use tracing::{info, debug, warn};
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone)]
+pub struct MockSettings {
+    /// Address to listen on
+    pub addr: SocketAddr,
+    /// Folder of order fixtures (one order or one orders response per JSON file)
+    pub fixtures: String,
+    /// Number of orders per page when the client does not ask for one
+    pub page_size: usize,
+    /// Fail every nth request
+    pub fail_every: Option<usize>,
+    /// Status code of the injected failures
+    pub fail_status: u16,
+    /// Only inject failures on paths starting with this prefix
+    pub fail_path: Option<String>,
+}
+
+#[derive(Debug)]
+struct MockDocument {
+    id: u64,
+    order_id: String,
+    file_name: String,
+    type_code: String,
+    content: Vec<u8>,
+    date_uploaded: String,
+}
+
+#[derive(Debug)]
+struct MockImport {
+    id: u64,
+    /// `offers` or `products`
+    kind: String,
+    /// Number of lines of the imported file, without the header
+    lines: usize,
+}
+
+#[derive(Debug)]
+struct MockState {
+    settings: MockSettings,
+    orders: Vec<Value>,
+    documents: Vec<MockDocument>,
+    imports: Vec<MockImport>,
+    /// State of the return lines that were updated, by return line id
+    return_line_states: HashMap<String, String>,
+    request_count: usize,
+}
+
+impl MockState {
+    fn load(settings: MockSettings) -> Result<Self> {
+        let mut orders = vec![];
+        let mut paths = std::fs::read_dir(&settings.fixtures)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        paths.sort();
+        for path in paths.iter().filter(|p| p.extension() == Some("json".as_ref())) {
+            let fixture: Value = serde_json::from_reader(std::fs::File::open(path)?)?;
+            match fixture.get("orders").and_then(Value::as_array) {
+                Some(fixture_orders) => orders.extend(fixture_orders.iter().cloned()),
+                None => orders.push(fixture),
+            }
+        }
+        info!("Loaded {} fixture orders from {}", orders.len(), Path::new(&settings.fixtures).display());
+
+        Ok(Self {
+            settings,
+            orders,
+            documents: vec![],
+            imports: vec![],
+            return_line_states: HashMap::new(),
+            request_count: 0,
+        })
+    }
+
+    fn order_mut(&mut self, order_id: &str) -> Option<&mut Value> {
+        self.orders.iter_mut().find(|o| o["order_id"] == order_id)
+    }
+
+    /// Orders that can be returned and have been paid for
+    fn shipped_orders(&self) -> impl Iterator<Item = &Value> {
+        self.orders.iter().filter(|o| matches!(o["order_state"].as_str(), Some("SHIPPED" | "RECEIVED" | "CLOSED")))
+    }
+
+    fn should_fail(&mut self, path: &str) -> bool {
+        if let Some(prefix) = &self.settings.fail_path {
+            if !path.starts_with(prefix.as_str()) {
+                return false;
+            }
+        }
+        self.request_count += 1;
+        matches!(self.settings.fail_every, Some(n) if n > 0 && self.request_count.rem_euclid(n) == 0)
+    }
+}
+
+pub async fn run(settings: MockSettings) -> Result<()> {
+    let addr = settings.addr;
+    let state = Arc::new(Mutex::new(MockState::load(settings)?));
+
+    let make_service = make_service_fn(move |_| {
+        let state = state.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request)))
+        }
+    });
+    let server = hyper::Server::try_bind(&addr)?.serve(make_service);
+    // the port is only known once bound when listening on port 0
+    info!("Mock server listening on http://{}", server.local_addr());
+    server.await?;
+
+    Ok(())
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, json!({ "status": status.as_u16(), "message": message }))
+}
+
+async fn handle(state: Arc<Mutex<MockState>>, request: Request<Body>) -> std::result::Result<Response<Body>, Infallible> {
+    let method = request.method().clone();
+    let uri = request.uri().clone();
+    let host = request.headers()
+        .get(hyper::header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or("localhost")
+        .to_string();
+    let content_type = request.headers()
+        .get(hyper::header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body.to_vec(),
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, &e.to_string())),
+    };
+    // only used to parse the query
+    let url = match reqwest::Url::parse(&format!("http://{}{}", host, uri)) {
+        Ok(url) => url,
+        Err(e) => return Ok(error_response(StatusCode::BAD_REQUEST, &format!("Invalid host {}: {}", host, e))),
+    };
+    let query = url.query_pairs().into_owned().collect::<Vec<(String, String)>>();
+    let param = |name: &str| query.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone());
+
+    let mut state = state.lock().unwrap();
+    debug!("{} {}", method, uri);
+    if state.should_fail(uri.path()) {
+        let status = StatusCode::from_u16(state.settings.fail_status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        warn!("Injecting {} on {} {}", status, method, uri);
+        return Ok(error_response(status, "Injected failure"));
+    }
+
+    let segments = uri.path().trim_matches('/').split('/').collect::<Vec<_>>();
+    let response = match (&method, segments.as_slice()) {
+        (&Method::GET, ["api", "orders"]) => list_orders(&state, &url, &param),
+        (&Method::GET, ["api", "orders", "documents"]) => list_documents(&state, &param),
+        (&Method::GET, ["api", "orders", "documents", "download"]) => download_document(&state, &param),
+        (&Method::PUT, ["api", "orders", order_id, "accept"]) => accept_order(&mut state, order_id, &body),
+        (&Method::POST, ["api", "orders", order_id, "tracking"]) => update_tracking(&mut state, order_id, &body),
+        (&Method::PUT, ["api", "orders", order_id, "additional_fields"]) => update_additional_fields(&mut state, order_id, &body),
+        (&Method::GET | &Method::PUT, ["api", "orders", order_id, "ship"]) => ship_order(&mut state, order_id),
+        (&Method::POST, ["api", "orders", order_id, "documents"]) => upload_documents(&mut state, order_id, &content_type, &body),
+        (&Method::PUT, ["api", "orders", order_id, "lines", line_id, action]) => update_incident(&mut state, order_id, line_id, action),
+        (&Method::GET, ["api", "reasons", reason_type]) => list_reasons(reason_type),
+        (&Method::GET, ["api", "additional_fields"]) => list_additional_fields(&state),
+        (&Method::GET, ["api", "shipping", "carriers"]) => list_carriers(),
+        (&Method::POST, ["api", kind @ ("offers" | "products"), "imports"]) => upload_import(&mut state, kind, &content_type, &body),
+        (&Method::GET, ["api", kind @ ("offers" | "products"), "imports", import_id]) => import_status(&state, kind, import_id),
+        (&Method::GET, ["api", "returns"]) => list_returns(&state, &param),
+        (&Method::PUT, ["api", "returns", return_id, action]) => update_return(&mut state, return_id, action, &body),
+        (&Method::GET, ["api", "sellerpayment", "transactions_logs"]) => list_transactions(&state, &param),
+        // billing cycles are not simulated
+        (&Method::GET, ["api", "invoices"]) => json_response(StatusCode::OK, json!({ "invoices": [], "total_count": 0 })),
+        _ => error_response(StatusCode::NOT_FOUND, "Unknown endpoint"),
+    };
+
+    Ok(response)
+}
+
+fn list_orders(state: &MockState, url: &reqwest::Url, param: &dyn Fn(&str) -> Option<String>) -> Response<Body> {
+    let split = |value: Option<String>| value.map(|v| v.split(',').map(str::to_string).collect::<Vec<_>>());
+    let state_codes = split(param("order_state_codes"));
+    let order_ids = split(param("order_ids"));
+    let channel_codes = split(param("channel_codes"));
+    let has_incident = param("has_incident").map(|v| v == "true");
+
+    // a filter that was not given matches everything
+    let matches = |filter: &Option<Vec<String>>, value: &Value| match filter {
+        Some(values) => values.iter().any(|v| value == v.as_str()),
+        None => true,
+    };
+    let orders = state.orders.iter()
+        .filter(|o| matches(&state_codes, &o["order_state"]))
+        .filter(|o| matches(&order_ids, &o["order_id"]))
+        .filter(|o| matches(&channel_codes, &o["channel"]["code"]))
+        .filter(|o| has_incident.is_none() || o["has_incident"].as_bool() == has_incident)
+        .collect::<Vec<_>>();
+
+    let max = param("max").and_then(|m| m.parse().ok()).unwrap_or(state.settings.page_size);
+    let offset = param("offset").and_then(|o| o.parse().ok()).unwrap_or(0usize);
+    let page = orders.iter().skip(offset).take(max).cloned().cloned().collect::<Vec<_>>();
+
+    let mut response = json_response(StatusCode::OK, json!({ "orders": page, "total_count": orders.len() }));
+    if offset + max < orders.len() {
+        let mut next = url.clone();
+        let pairs = url.query_pairs()
+            .filter(|(k, _)| k != "offset" && k != "max")
+            .map(|(k, v)| (k.into_owned(), v.into_owned()))
+            .collect::<Vec<_>>();
+        next.query_pairs_mut()
+            .clear()
+            .extend_pairs(pairs)
+            .append_pair("max", &max.to_string())
+            .append_pair("offset", &(offset + max).to_string());
+        response.headers_mut().insert(
+            hyper::header::LINK,
+            format!("<{}>; rel=\"next\"", next).parse().unwrap(),
+        );
+    }
+    response
+}
+
+fn accept_order(state: &mut MockState, order_id: &str, body: &[u8]) -> Response<Body> {
+    let order = match state.order_mut(order_id) {
+        Some(order) => order,
+        None => return error_response(StatusCode::NOT_FOUND, "Order not found"),
+    };
+    if order["order_state"] != "WAITING_ACCEPTANCE" {
+        return error_response(StatusCode::BAD_REQUEST, "Order is not waiting for acceptance");
+    }
+
+    // no body accepts every line
+    let decisions: Value = serde_json::from_slice(body).unwrap_or_else(|_| json!({ "order_lines": [] }));
+    let refused = decisions["order_lines"].as_array()
+        .into_iter()
+        .flatten()
+        .any(|l| l["accepted"] == false);
+    let new_state = if refused { "REFUSED" } else { "SHIPPING" };
+    order["order_state"] = json!(new_state);
+    if let Some(lines) = order["order_lines"].as_array_mut() {
+        for line in lines {
+            line["order_line_state"] = json!(new_state);
+        }
+    }
+    info!("Order {} moved to {}", order_id, new_state);
+
+    Response::new(Body::empty())
+}
+
+fn update_tracking(state: &mut MockState, order_id: &str, body: &[u8]) -> Response<Body> {
+    let order = match state.order_mut(order_id) {
+        Some(order) => order,
+        None => return error_response(StatusCode::NOT_FOUND, "Order not found"),
+    };
+    let tracking: Value = match serde_json::from_slice(body) {
+        Ok(tracking) => tracking,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    if tracking["carrier_code"].is_null() && tracking["carrier_name"].is_null() {
+        return error_response(StatusCode::BAD_REQUEST, "carrier_code or carrier_name is required");
+    }
+
+    order["shipping_carrier_code"] = tracking["carrier_code"].clone();
+    order["shipping_company"] = tracking["carrier_name"].clone();
+    order["shipping_tracking"] = tracking["tracking_number"].clone();
+    order["shipping_tracking_url"] = tracking["carrier_url"].clone();
+    info!("Order {} tracking updated", order_id);
+
+    Response::new(Body::empty())
+}
+
+fn update_additional_fields(state: &mut MockState, order_id: &str, body: &[u8]) -> Response<Body> {
+    let order = match state.order_mut(order_id) {
+        Some(order) => order,
+        None => return error_response(StatusCode::NOT_FOUND, "Order not found"),
+    };
+    let update: Value = match serde_json::from_slice(body) {
+        Ok(update) => update,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+
+    // the request only carries code and value, new fields get a type guessed from the value
+    let merge = |fields: &mut Value, updates: &Value| {
+        if !fields.is_array() {
+            *fields = json!([]);
+        }
+        let fields = fields.as_array_mut().unwrap();
+        for field in updates.as_array().into_iter().flatten() {
+            match fields.iter_mut().find(|f| f["code"] == field["code"]) {
+                Some(existing) => existing["value"] = field["value"].clone(),
+                None => {
+                    let field_type = if field["value"].is_array() { "MULTIPLE_VALUES_LIST" } else { "STRING" };
+                    fields.push(json!({ "code": field["code"], "type": field_type, "value": field["value"] }));
+                },
+            }
+        }
+    };
+    merge(&mut order["order_additional_fields"], &update["order_additional_fields"]);
+    for line_update in update["order_lines"].as_array().into_iter().flatten() {
+        let line = order["order_lines"].as_array_mut()
+            .and_then(|lines| lines.iter_mut().find(|l| l["order_line_id"] == line_update["order_line_id"]));
+        match line {
+            Some(line) => merge(&mut line["order_line_additional_fields"], &line_update["order_line_additional_fields"]),
+            None => return error_response(StatusCode::BAD_REQUEST, "Order line not found"),
+        }
+    }
+    info!("Order {} additional fields updated", order_id);
+
+    Response::new(Body::empty())
+}
+
+/// Defines every additional field found on the fixture orders
+fn list_additional_fields(state: &MockState) -> Response<Body> {
+    let mut definitions: Vec<Value> = vec![];
+    let mut define = |entity: &str, fields: &Value| {
+        for field in fields.as_array().into_iter().flatten() {
+            if !definitions.iter().any(|d| d["entity"] == entity && d["code"] == field["code"]) {
+                definitions.push(json!({
+                    "code": field["code"],
+                    "label": field["code"],
+                    "type": field["type"],
+                    "entity": entity,
+                    "required": false,
+                }));
+            }
+        }
+    };
+    for order in &state.orders {
+        define("ORDER", &order["order_additional_fields"]);
+        for line in order["order_lines"].as_array().into_iter().flatten() {
+            define("ORDER_LINE", &line["order_line_additional_fields"]);
+        }
+    }
+
+    json_response(StatusCode::OK, json!({ "additional_fields": definitions }))
+}
+
+fn ship_order(state: &mut MockState, order_id: &str) -> Response<Body> {
+    let order = match state.order_mut(order_id) {
+        Some(order) => order,
+        None => return error_response(StatusCode::NOT_FOUND, "Order not found"),
+    };
+    if order["order_state"] != "SHIPPING" {
+        return error_response(StatusCode::BAD_REQUEST, "Order is not in SHIPPING");
+    }
+
+    order["order_state"] = json!("SHIPPED");
+    if let Some(lines) = order["order_lines"].as_array_mut() {
+        for line in lines {
+            line["order_line_state"] = json!("SHIPPED");
+        }
+    }
+    info!("Order {} moved to SHIPPED", order_id);
+
+    Response::new(Body::empty())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Splits a multipart body into (headers, content) pairs.
+fn multipart_parts<'a>(content_type: &str, body: &'a [u8]) -> Vec<(String, &'a [u8])> {
+    let boundary = match content_type.split("boundary=").nth(1) {
+        Some(boundary) => boundary,
+        None => return vec![],
+    };
+    let delimiter = format!("--{}", boundary.trim_matches('"'));
+    let mut parts = vec![];
+    let mut rest = body;
+    while let Some(start) = find(rest, delimiter.as_bytes()) {
+        rest = &rest[start + delimiter.len()..];
+        let end = match find(rest, delimiter.as_bytes()) {
+            Some(end) => end,
+            None => break,
+        };
+        let part = &rest[..end];
+        if let Some(split) = find(part, b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&part[..split]).to_string();
+            let content = part[split + 4..].strip_suffix(b"\r\n").unwrap_or(&part[split + 4..]);
+            parts.push((headers, content));
+        }
+    }
+    parts
+}
+
+fn upload_documents(state: &mut MockState, order_id: &str, content_type: &str, body: &[u8]) -> Response<Body> {
+    if state.order_mut(order_id).is_none() {
+        return error_response(StatusCode::NOT_FOUND, "Order not found");
+    }
+
+    let parts = multipart_parts(content_type, body);
+    let document_info = parts.iter()
+        .find(|(headers, _)| headers.contains("name=\"order_documents\""))
+        .and_then(|(_, content)| serde_json::from_slice::<Value>(content).ok())
+        .unwrap_or_else(|| json!({ "order_documents": [] }));
+    for (headers, content) in parts.iter().filter(|(headers, _)| headers.contains("name=\"files\"")) {
+        let file_name = headers.split("filename=\"").nth(1)
+            .and_then(|f| f.split('"').next())
+            .unwrap_or("document")
+            .to_string();
+        let type_code = document_info["order_documents"].as_array()
+            .and_then(|docs| docs.iter().find(|d| d["file_name"] == file_name.as_str()))
+            .and_then(|d| d["type_code"].as_str())
+            .unwrap_or("CUSTOMER_INVOICE")
+            .to_string();
+        let id = state.documents.len() as u64 + 1;
+        info!("Order {} received document {} ({})", order_id, file_name, type_code);
+        state.documents.push(MockDocument {
+            id,
+            order_id: order_id.to_string(),
+            file_name,
+            type_code,
+            content: content.to_vec(),
+            date_uploaded: chrono::Utc::now().to_rfc3339(),
+        });
+    }
+    if let Some(order) = state.order_mut(order_id) {
+        order["has_invoice"] = json!(true);
+    }
+
+    json_response(StatusCode::OK, json!({ "errors_count": 0, "order_documents": [] }))
+}
+
+fn list_documents(state: &MockState, param: &dyn Fn(&str) -> Option<String>) -> Response<Body> {
+    let order_ids = param("order_ids").unwrap_or_default();
+    let order_ids = order_ids.split(',').collect::<Vec<_>>();
+    let documents = state.documents.iter()
+        .filter(|d| order_ids.contains(&d.order_id.as_str()))
+        .map(|d| json!({
+            "id": d.id,
+            "order_id": d.order_id,
+            "file_name": d.file_name,
+            "file_size": d.content.len(),
+            "type": d.type_code,
+            "date_uploaded": d.date_uploaded,
+        }))
+        .collect::<Vec<_>>();
+
+    json_response(StatusCode::OK, json!({ "total_count": documents.len(), "order_documents": documents }))
+}
+
+fn download_document(state: &MockState, param: &dyn Fn(&str) -> Option<String>) -> Response<Body> {
+    let document = param("document_ids")
+        .and_then(|id| id.parse::<u64>().ok())
+        .and_then(|id| state.documents.iter().find(|d| d.id == id));
+    match document {
+        Some(document) => Response::builder()
+            .header("Content-Disposition", format!("attachment; filename=\"{}\"", document.file_name))
+            .body(Body::from(document.content.clone()))
+            .unwrap(),
+        None => error_response(StatusCode::NOT_FOUND, "Document not found"),
+    }
+}
+
+fn update_incident(state: &mut MockState, order_id: &str, line_id: &str, action: &str) -> Response<Body> {
+    let order = match state.order_mut(order_id) {
+        Some(order) => order,
+        None => return error_response(StatusCode::NOT_FOUND, "Order not found"),
+    };
+    let new_state = match action {
+        "open_incident" => "INCIDENT_OPEN",
+        "close_incident" => "INCIDENT_CLOSED",
+        _ => return error_response(StatusCode::NOT_FOUND, "Unknown endpoint"),
+    };
+    let line = match order["order_lines"].as_array_mut().and_then(|lines| lines.iter_mut().find(|l| l["order_line_id"] == line_id)) {
+        Some(line) => line,
+        None => return error_response(StatusCode::NOT_FOUND, "Order line not found"),
+    };
+    line["order_line_state"] = json!(new_state);
+    let has_incident = order["order_lines"].as_array()
+        .into_iter()
+        .flatten()
+        .any(|l| l["order_line_state"] == "INCIDENT_OPEN");
+    order["has_incident"] = json!(has_incident);
+    info!("Order line {} of order {} moved to {}", line_id, order_id, new_state);
+
+    Response::new(Body::empty())
+}
+
+fn list_carriers() -> Response<Body> {
+    json_response(StatusCode::OK, json!({
+        "carriers": [
+            { "code": "DHL", "label": "DHL", "tracking_url": "https://www.dhl.com/track?id={trackingId}" },
+            { "code": "UPS", "label": "UPS", "tracking_url": "https://www.ups.com/track?tracknum={trackingId}" },
+            { "code": "PICKUP", "label": "Store pickup", "tracking_url": null },
+        ]
+    }))
+}
+
+fn list_reasons(reason_type: &str) -> Response<Body> {
+    let reasons = match reason_type {
+        "INCIDENT_OPEN" => json!([
+            { "code": "16", "label": "Damaged item" },
+            { "code": "17", "label": "Item not received" },
+        ]),
+        "INCIDENT_CLOSE" => json!([
+            { "code": "20", "label": "Incident resolved" },
+        ]),
+        _ => json!([]),
+    };
+
+    json_response(StatusCode::OK, json!({ "reasons": reasons }))
+}
+
+fn upload_import(state: &mut MockState, kind: &str, content_type: &str, body: &[u8]) -> Response<Body> {
+    let parts = multipart_parts(content_type, body);
+    let file = match parts.iter().find(|(headers, _)| headers.contains("name=\"file\"")) {
+        Some((_, file)) => file,
+        None => return error_response(StatusCode::BAD_REQUEST, "file is required"),
+    };
+    let lines = String::from_utf8_lossy(file).lines().filter(|l| !l.trim().is_empty()).count().saturating_sub(1);
+    let id = state.imports.len() as u64 + 1;
+    info!("Received {} import {} with {} lines", kind, id, lines);
+    state.imports.push(MockImport { id, kind: kind.to_string(), lines });
+
+    json_response(StatusCode::CREATED, json!({ "import_id": id }))
+}
+
+/// Imports complete right away, with every line in success
+fn import_status(state: &MockState, kind: &str, import_id: &str) -> Response<Body> {
+    let import = import_id.parse::<u64>().ok()
+        .and_then(|id| state.imports.iter().find(|i| i.id == id && i.kind == kind));
+    match import {
+        Some(import) if kind == "offers" => json_response(StatusCode::OK, json!({
+            "import_id": import.id,
+            "status": "COMPLETE",
+            "has_error_report": false,
+            "lines_read": import.lines,
+            "lines_in_success": import.lines,
+            "lines_in_error": 0,
+            "offer_inserted": 0,
+            "offer_updated": import.lines,
+            "offer_deleted": 0,
+        })),
+        Some(import) => json_response(StatusCode::OK, json!({
+            "import_id": import.id,
+            "import_status": "COMPLETE",
+            "has_error_report": false,
+            "has_new_product_report": false,
+            "has_transformed_file": false,
+            "has_transformation_error_report": false,
+            "transform_lines_read": import.lines,
+            "transform_lines_in_success": import.lines,
+            "transform_lines_in_error": 0,
+            "transform_lines_with_warning": 0,
+        })),
+        None => error_response(StatusCode::NOT_FOUND, "Import not found"),
+    }
+}
+
+/// Every shipped order is returned, with all its lines
+fn mock_returns(state: &MockState) -> Vec<Value> {
+    state.shipped_orders()
+        .map(|order| {
+            let lines = order["order_lines"].as_array()
+                .into_iter()
+                .flatten()
+                .map(|line| {
+                    let id = format!("R-{}", line["order_line_id"].as_str().unwrap_or_default());
+                    let line_state = state.return_line_states.get(&id).map(String::as_str).unwrap_or("WAITING_RECEPTION");
+                    json!({
+                        "id": id,
+                        "order_line_id": line["order_line_id"],
+                        "offer_sku": line["offer_sku"],
+                        "quantity": line["quantity"],
+                        "state": line_state,
+                    })
+                })
+                .collect::<Vec<_>>();
+            // a return is in the state of its lines until they part ways
+            let return_state = match lines.first() {
+                Some(first) if lines.iter().all(|l| l["state"] == first["state"]) => first["state"].clone(),
+                _ => json!("IN_PROGRESS"),
+            };
+            json!({
+                "id": format!("R-{}", order["order_id"].as_str().unwrap_or_default()),
+                "order_id": order["order_id"],
+                "order_commercial_id": order["commercial_id"],
+                "state": return_state,
+                "date_created": order["created_date"],
+                "return_lines": lines,
+            })
+        })
+        .collect()
+}
+
+fn list_returns(state: &MockState, param: &dyn Fn(&str) -> Option<String>) -> Response<Body> {
+    let states = param("states").map(|states| states.split(',').map(str::to_string).collect::<Vec<_>>());
+    let returns = mock_returns(state).into_iter()
+        .filter(|r| states.as_ref().map(|states| states.iter().any(|s| r["state"] == s.as_str())).unwrap_or(true))
+        .collect::<Vec<_>>();
+
+    json_response(StatusCode::OK, json!({ "data": returns, "next_page_token": null }))
+}
+
+fn update_return(state: &mut MockState, return_id: &str, action: &str, body: &[u8]) -> Response<Body> {
+    let new_state = match action {
+        "receive" => "RECEIVED",
+        "accept" => "ACCEPTED",
+        "refuse" => "REFUSED",
+        _ => return error_response(StatusCode::NOT_FOUND, "Unknown endpoint"),
+    };
+    let line_ids = match mock_returns(state).iter().find(|r| r["id"] == return_id) {
+        Some(ret) => ret["return_lines"].as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|l| l["id"].as_str().map(str::to_string))
+            .collect::<Vec<_>>(),
+        None => return error_response(StatusCode::NOT_FOUND, "Return not found"),
+    };
+    let update: Value = match serde_json::from_slice(body) {
+        Ok(update) => update,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, &e.to_string()),
+    };
+    let updated = update["return_lines"].as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|l| l["id"].as_str())
+        .collect::<Vec<_>>();
+    if let Some(unknown) = updated.iter().find(|id| !line_ids.iter().any(|line_id| line_id == *id)) {
+        return error_response(StatusCode::BAD_REQUEST, &format!("Return line {} not found", unknown));
+    }
+    for id in updated {
+        state.return_line_states.insert(id.to_string(), new_state.to_string());
+    }
+    info!("Return {} lines moved to {}", return_id, new_state);
+
+    Response::new(Body::empty())
+}
+
+/// Bounds are either dates or date times
+fn parse_bound(value: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    chrono::DateTime::parse_from_rfc3339(value)
+        .or_else(|_| chrono::DateTime::parse_from_rfc3339(&format!("{}T00:00:00Z", value)))
+        .ok()
+}
+
+/// Each shipped order is paid and charged a commission
+fn list_transactions(state: &MockState, param: &dyn Fn(&str) -> Option<String>) -> Response<Body> {
+    let from = param("date_created_from").as_deref().and_then(parse_bound);
+    let to = param("date_created_to").as_deref().and_then(parse_bound);
+    let transactions = state.shipped_orders()
+        .flat_map(|order| {
+            let order_id = order["order_id"].as_str().unwrap_or_default();
+            let commission = -order["total_commission"].as_f64().unwrap_or_default();
+            [("ORDER_AMOUNT", order["total_price"].as_f64().unwrap_or_default()), ("COMMISSION_FEE", commission)]
+                .into_iter()
+                .enumerate()
+                .map(move |(index, (transaction_type, amount))| json!({
+                    "id": format!("T-{}-{}", order_id, index + 1),
+                    "type": transaction_type,
+                    "amount": amount,
+                    "currency_iso_code": order["currency_iso_code"],
+                    "date_created": order["created_date"],
+                    "payment_state": "PENDING",
+                    "entities": { "order": { "id": order_id } },
+                }))
+        })
+        .filter(|t| {
+            let created = t["date_created"].as_str().and_then(parse_bound);
+            from.map(|from| created >= Some(from)).unwrap_or(true) && to.map(|to| created <= Some(to)).unwrap_or(true)
+        })
+        .collect::<Vec<_>>();
+
+    let limit = param("limit").and_then(|l| l.parse().ok()).unwrap_or(state.settings.page_size);
+    let offset = param("page_token").and_then(|t| t.parse().ok()).unwrap_or(0usize);
+    let page = transactions.iter().skip(offset).take(limit).collect::<Vec<_>>();
+    let next_page_token = (offset + limit < transactions.len()).then(|| (offset + limit).to_string());
+
+    json_response(StatusCode::OK, json!({ "data": page, "next_page_token": next_page_token }))
+}
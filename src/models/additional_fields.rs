@@ -0,0 +1,393 @@
+use serde::{Deserialize, Serialize};
+
+use super::{orders::{AdditionalField, Order}, unknown::{strip_unknown_fields, UnknownFields}};
+
+/// Type of an additional field, as named by the marketplace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum AdditionalFieldType {
+    Boolean,
+    Date,
+    Link,
+    List,
+    MultipleValuesList,
+    Numeric,
+    Regex,
+    String,
+    Textarea,
+}
+
+/// One line of an additional fields file
+#[derive(Debug, Deserialize)]
+pub struct AdditionalFieldRecord {
+    /// Order's identifier
+    pub order_id: String,
+    /// Order line's identifier, empty for a field of the order itself
+    pub order_line_id: Option<String>,
+    /// Additional field's code
+    pub code: String,
+    /// Additional field's type, taken from the marketplace's definition when empty
+    #[serde(rename = "type")]
+    pub field_type: Option<AdditionalFieldType>,
+    /// Additional field's value, the values of a MULTIPLE_VALUES_LIST field are separated by `|`
+    pub value: String,
+}
+
+/// Value of an additional field, parsed according to its type
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedValue<'a> {
+    Boolean(bool),
+    Date(chrono::DateTime<chrono::FixedOffset>),
+    Link(reqwest::Url),
+    Numeric(f64),
+    Text(&'a str),
+    Values(&'a [String]),
+}
+
+impl AdditionalField {
+    /// Builds a field of the given type from its textual value, checking the value fits the type
+    pub fn parse(field_type: AdditionalFieldType, code: String, value: &str) -> Result<Self, String> {
+        let value = value.trim().to_string();
+        let field = match field_type {
+            AdditionalFieldType::Boolean => AdditionalField::Boolean { code, value },
+            AdditionalFieldType::Date => AdditionalField::Date { code, value },
+            AdditionalFieldType::Link => AdditionalField::Link { code, value },
+            AdditionalFieldType::List => AdditionalField::List { code, value },
+            AdditionalFieldType::MultipleValuesList => AdditionalField::MultipleValuesList {
+                code,
+                value: value.split('|').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect(),
+            },
+            AdditionalFieldType::Numeric => AdditionalField::Numeric { code, value },
+            AdditionalFieldType::Regex => AdditionalField::Regex { code, value },
+            AdditionalFieldType::String => AdditionalField::String { code, value },
+            AdditionalFieldType::Textarea => AdditionalField::Textarea { code, value },
+        };
+        field.typed_value()?;
+        Ok(field)
+    }
+
+    pub fn code(&self) -> &str {
+        match self {
+            AdditionalField::Boolean { code, .. }
+            | AdditionalField::Date { code, .. }
+            | AdditionalField::Link { code, .. }
+            | AdditionalField::List { code, .. }
+            | AdditionalField::MultipleValuesList { code, .. }
+            | AdditionalField::Numeric { code, .. }
+            | AdditionalField::Regex { code, .. }
+            | AdditionalField::String { code, .. }
+            | AdditionalField::Textarea { code, .. } => code,
+        }
+    }
+
+    pub fn field_type(&self) -> AdditionalFieldType {
+        match self {
+            AdditionalField::Boolean { .. } => AdditionalFieldType::Boolean,
+            AdditionalField::Date { .. } => AdditionalFieldType::Date,
+            AdditionalField::Link { .. } => AdditionalFieldType::Link,
+            AdditionalField::List { .. } => AdditionalFieldType::List,
+            AdditionalField::MultipleValuesList { .. } => AdditionalFieldType::MultipleValuesList,
+            AdditionalField::Numeric { .. } => AdditionalFieldType::Numeric,
+            AdditionalField::Regex { .. } => AdditionalFieldType::Regex,
+            AdditionalField::String { .. } => AdditionalFieldType::String,
+            AdditionalField::Textarea { .. } => AdditionalFieldType::Textarea,
+        }
+    }
+
+    /// Parses the value according to the field's type
+    pub fn typed_value(&self) -> Result<TypedValue<'_>, String> {
+        let invalid = |value: &str, expected: &str| format!("{} is not a valid {} for field {}", value, expected, self.code());
+        match self {
+            AdditionalField::Boolean { value, .. } => parse_boolean(value)
+                .map(TypedValue::Boolean)
+                .ok_or_else(|| invalid(value, "boolean (true or false)")),
+            AdditionalField::Date { value, .. } => chrono::DateTime::parse_from_rfc3339(value)
+                .map(TypedValue::Date)
+                .map_err(|_| invalid(value, "date (ISO 8601 with time zone)")),
+            AdditionalField::Link { value, .. } => match reqwest::Url::parse(value) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(TypedValue::Link(url)),
+                _ => Err(invalid(value, "link (http or https URL)")),
+            },
+            AdditionalField::Numeric { value, .. } => parse_numeric(value)
+                .map(TypedValue::Numeric)
+                .ok_or_else(|| invalid(value, "number")),
+            AdditionalField::MultipleValuesList { value, .. } => Ok(TypedValue::Values(value)),
+            AdditionalField::List { value, .. }
+            | AdditionalField::Regex { value, .. }
+            | AdditionalField::String { value, .. }
+            | AdditionalField::Textarea { value, .. } => Ok(TypedValue::Text(value)),
+        }
+    }
+
+    /// Checks the field against its definition on the marketplace
+    pub fn check(&self, definition: &AdditionalFieldDefinition) -> Result<(), String> {
+        if self.field_type() != definition.field_type {
+            return Err(format!("field {} is a {:?} field, not {:?}", self.code(), definition.field_type, self.field_type()));
+        }
+        let values = match self.typed_value()? {
+            TypedValue::Text(value) => vec![value],
+            TypedValue::Values(values) => values.iter().map(String::as_str).collect(),
+            _ => return Ok(()),
+        };
+        if let Some(accepted_values) = &definition.accepted_values {
+            if let Some(value) = values.iter().find(|value| !accepted_values.iter().any(|accepted| accepted == *value)) {
+                return Err(format!("{} is not an accepted value for field {} ({})", value, self.code(), accepted_values.join(", ")));
+            }
+        }
+        if let Some(pattern) = &definition.regex {
+            // the pattern has to match the whole value
+            let regex = regex::Regex::new(&format!("^(?:{})$", pattern))
+                .map_err(|e| format!("pattern of field {} is invalid: {}", self.code(), e))?;
+            if let Some(value) = values.iter().find(|value| !regex.is_match(value)) {
+                return Err(format!("{} does not match the pattern {} of field {}", value, pattern, self.code()));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_boolean(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_numeric(value: &str) -> Option<f64> {
+    value.parse::<f64>().ok().filter(|number| number.is_finite())
+}
+
+/// Writes a BOOLEAN value as a boolean, or as is when it is not one
+pub(crate) fn serialize_boolean<S: serde::Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    match parse_boolean(value) {
+        Some(boolean) => serializer.serialize_bool(boolean),
+        None => serializer.serialize_str(value),
+    }
+}
+
+/// Reads a value sent as text, or written as a boolean or number by the exports
+pub(crate) fn deserialize_text<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawValue {
+        Text(String),
+        Boolean(bool),
+        Number(serde_json::Number),
+    }
+
+    Ok(match RawValue::deserialize(deserializer)? {
+        RawValue::Text(value) => value,
+        RawValue::Boolean(value) => value.to_string(),
+        RawValue::Number(value) => value.to_string(),
+    })
+}
+
+/// Definition of an additional field, as configured on the marketplace
+#[skip_serializing_none]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AdditionalFieldDefinition {
+    /// Additional field's code
+    pub code: String,
+    /// Additional field's label
+    pub label: Option<String>,
+    /// Additional field's type
+    #[serde(rename = "type")]
+    pub field_type: AdditionalFieldType,
+    /// Entity the field is set on (ORDER, ORDER_LINE...)
+    pub entity: String,
+    /// Whether the field must have a value
+    #[serde(default)]
+    pub required: bool,
+    /// Values accepted by LIST and MULTIPLE_VALUES_LIST fields
+    pub accepted_values: Option<Vec<String>>,
+    /// Pattern the values of REGEX fields must match
+    pub regex: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+strip_unknown_fields! {
+    AdditionalFieldDefinition {},
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AdditionalFieldDefinitionResponse {
+    /// Additional fields defined on the marketplace
+    pub additional_fields: Vec<AdditionalFieldDefinition>,
+}
+
+impl AdditionalFieldDefinitionResponse {
+    pub fn find(&self, entity: &str, code: &str) -> Option<&AdditionalFieldDefinition> {
+        self.additional_fields.iter().find(|definition| definition.entity == entity && definition.code == code)
+    }
+}
+
+/// Checks the additional fields of an order and of its lines, returns the problems found.
+///
+/// Without definitions only the values are checked against their types.
+pub fn check_order(order: &Order, definitions: Option<&AdditionalFieldDefinitionResponse>) -> Vec<String> {
+    let mut problems = check_fields("ORDER", order.order_additional_fields.as_deref().unwrap_or_default(), definitions)
+        .into_iter()
+        .map(|problem| format!("order {}: {}", order.order_id, problem))
+        .collect::<Vec<_>>();
+    for line in &order.order_lines {
+        problems.extend(check_fields("ORDER_LINE", &line.order_line_additional_fields, definitions)
+            .into_iter()
+            .map(|problem| format!("order line {}: {}", line.order_line_id, problem)));
+    }
+    problems
+}
+
+fn check_fields(entity: &str, fields: &[AdditionalField], definitions: Option<&AdditionalFieldDefinitionResponse>) -> Vec<String> {
+    let definitions = match definitions {
+        Some(definitions) => definitions,
+        None => return fields.iter().filter_map(|field| field.typed_value().err()).collect(),
+    };
+
+    let mut problems = vec![];
+    for field in fields {
+        let result = match definitions.find(entity, field.code()) {
+            Some(definition) => field.check(definition),
+            None => Err(format!("field {} is not defined on the marketplace", field.code())),
+        };
+        problems.extend(result.err());
+    }
+    for definition in definitions.additional_fields.iter().filter(|d| d.required && d.entity == entity) {
+        if !fields.iter().any(|field| field.code() == definition.code) {
+            problems.push(format!("required field {} is missing", definition.code));
+        }
+    }
+    problems
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct AdditionalFieldsRequest {
+    /// Additional fields of the order
+    pub order_additional_fields: Vec<AdditionalFieldUpdate>,
+    /// Additional fields of the order lines
+    pub order_lines: Vec<OrderLineAdditionalFields>,
+}
+
+impl AdditionalFieldsRequest {
+    /// Adds a field to the order, or to one of its lines
+    pub fn add(&mut self, order_line_id: Option<String>, field: AdditionalField) {
+        let update = AdditionalFieldUpdate::from(field);
+        let order_line_id = match order_line_id {
+            Some(order_line_id) => order_line_id,
+            None => return self.order_additional_fields.push(update),
+        };
+        match self.order_lines.iter_mut().find(|line| line.order_line_id == order_line_id) {
+            Some(line) => line.order_line_additional_fields.push(update),
+            None => self.order_lines.push(OrderLineAdditionalFields {
+                order_line_id,
+                order_line_additional_fields: vec![update],
+            }),
+        }
+    }
+
+    /// Number of fields updated by the request
+    pub fn field_count(&self) -> usize {
+        self.order_additional_fields.len()
+            + self.order_lines.iter().map(|line| line.order_line_additional_fields.len()).sum::<usize>()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderLineAdditionalFields {
+    /// Order line's identifier
+    pub order_line_id: String,
+    /// Additional fields of the order line
+    pub order_line_additional_fields: Vec<AdditionalFieldUpdate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdditionalFieldUpdate {
+    /// Additional field's code
+    pub code: String,
+    /// Additional field's value
+    pub value: AdditionalFieldValue,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum AdditionalFieldValue {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl From<AdditionalField> for AdditionalFieldUpdate {
+    fn from(field: AdditionalField) -> Self {
+        let (code, value) = match field {
+            AdditionalField::MultipleValuesList { code, value } => (code, AdditionalFieldValue::Multiple(value)),
+            AdditionalField::Boolean { code, value }
+            | AdditionalField::Date { code, value }
+            | AdditionalField::Link { code, value }
+            | AdditionalField::List { code, value }
+            | AdditionalField::Numeric { code, value }
+            | AdditionalField::Regex { code, value }
+            | AdditionalField::String { code, value }
+            | AdditionalField::Textarea { code, value } => (code, AdditionalFieldValue::Single(value)),
+        };
+        Self { code, value }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(field_type: AdditionalFieldType, accepted_values: Option<&[&str]>, regex: Option<&str>) -> AdditionalFieldDefinition {
+        AdditionalFieldDefinition {
+            code: "field".to_string(),
+            label: None,
+            field_type,
+            entity: "ORDER".to_string(),
+            required: false,
+            accepted_values: accepted_values.map(|values| values.iter().map(|v| v.to_string()).collect()),
+            regex: regex.map(str::to_string),
+            extra: UnknownFields::default(),
+        }
+    }
+
+    #[test]
+    fn typed_values_are_parsed() {
+        let parse = |field_type, value| AdditionalField::parse(field_type, "field".to_string(), value);
+        assert_eq!(parse(AdditionalFieldType::Boolean, "true").unwrap().typed_value(), Ok(TypedValue::Boolean(true)));
+        assert_eq!(parse(AdditionalFieldType::Numeric, " 12.5 ").unwrap().typed_value(), Ok(TypedValue::Numeric(12.5)));
+        assert!(matches!(parse(AdditionalFieldType::Date, "2022-12-03T09:00:00+01:00").unwrap().typed_value(), Ok(TypedValue::Date(_))));
+        assert!(matches!(parse(AdditionalFieldType::Link, "https://example.com/a").unwrap().typed_value(), Ok(TypedValue::Link(_))));
+
+        assert!(parse(AdditionalFieldType::Boolean, "yes").is_err());
+        assert!(parse(AdditionalFieldType::Numeric, "NaN").is_err());
+        assert!(parse(AdditionalFieldType::Date, "2022-12-03").is_err());
+        assert!(parse(AdditionalFieldType::Link, "example.com").is_err());
+        assert!(parse(AdditionalFieldType::Link, "ftp://example.com").is_err());
+    }
+
+    #[test]
+    fn fields_are_checked_against_definitions() {
+        let list = AdditionalField::parse(AdditionalFieldType::MultipleValuesList, "field".to_string(), "a|b").unwrap();
+        assert!(list.check(&definition(AdditionalFieldType::MultipleValuesList, Some(&["a", "b", "c"]), None)).is_ok());
+        assert!(list.check(&definition(AdditionalFieldType::MultipleValuesList, Some(&["a"]), None)).is_err());
+        assert!(list.check(&definition(AdditionalFieldType::List, None, None)).is_err());
+
+        let reference = AdditionalField::parse(AdditionalFieldType::Regex, "field".to_string(), "PO-123").unwrap();
+        assert!(reference.check(&definition(AdditionalFieldType::Regex, None, Some("PO-[0-9]+"))).is_ok());
+        assert!(reference.check(&definition(AdditionalFieldType::Regex, None, Some("[0-9]+"))).is_err());
+    }
+
+    #[test]
+    fn numeric_values_are_exported_as_written() {
+        for value in ["2.50", "007"] {
+            let field = AdditionalField::parse(AdditionalFieldType::Numeric, "weight".to_string(), value).unwrap();
+            let json = serde_json::to_value(&field).unwrap();
+            assert_eq!(json, serde_json::json!({ "type": "NUMERIC", "code": "weight", "value": value }));
+        }
+
+        // exports of earlier versions wrote numbers
+        let read: AdditionalField = serde_json::from_value(serde_json::json!({ "type": "NUMERIC", "code": "weight", "value": 2.5 })).unwrap();
+        assert_eq!(read.typed_value(), Ok(TypedValue::Numeric(2.5)));
+    }
+}
@@ -0,0 +1,38 @@
+use serde::Deserialize;
+
+/// Body of an error response of the API
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiErrorResponse {
+    /// HTTP status, repeated in the body
+    pub status: Option<u16>,
+    /// What went wrong
+    pub message: Option<String>,
+    /// Fields of the request that were rejected
+    pub errors: Vec<FieldError>,
+}
+
+/// A field of the request rejected by the API
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct FieldError {
+    /// Path of the field in the request, e.g. `order_lines[0].quantity`
+    pub field: Option<String>,
+    /// Error code
+    pub code: Option<String>,
+    /// Why the field was rejected
+    pub message: String,
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{}: {}", field, self.message)?,
+            None => write!(f, "{}", self.message)?,
+        }
+        if let Some(code) = &self.code {
+            write!(f, " ({})", code)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, ParseError, Utc};
+use serde::Serialize;
+
+use super::orders::Order;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadlineAlert {
+    /// Order's identifier
+    pub order_id: String,
+    /// Commercial order's identifier
+    pub commercial_id: String,
+    /// Order's shipping deadline
+    pub shipping_deadline: String,
+    /// Whole hours left until the deadline, negative when it already passed
+    pub hours_left: i64,
+    /// Minutes left until the deadline, negative when it already passed
+    pub minutes_left: i64,
+    /// Whether the deadline already passed
+    pub overdue: bool,
+}
+
+impl DeadlineAlert {
+    /// Returns an alert if the order's shipping deadline is within `window_hours` of `now` or already passed,
+    /// or the error of a deadline that can't be read.
+    pub fn check(order: &Order, now: DateTime<Utc>, window_hours: i64) -> Result<Option<Self>, ParseError> {
+        let deadline = match &order.shipping_deadline {
+            Some(deadline) => deadline,
+            None => return Ok(None),
+        };
+        let deadline_date = DateTime::parse_from_rfc3339(deadline)?.with_timezone(&Utc);
+        // whole hours would put an order 59 minutes past the window inside it
+        let minutes_left = (deadline_date - now).num_minutes();
+        if minutes_left > window_hours * 60 {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            order_id: order.order_id.clone(),
+            commercial_id: order.commercial_id.clone(),
+            shipping_deadline: deadline.clone(),
+            hours_left: minutes_left / 60,
+            minutes_left,
+            overdue: deadline_date <= now,
+        }))
+    }
+
+    /// Time left until the deadline, e.g. `0h59m` or `-2h05m`
+    pub fn time_left(&self) -> String {
+        let sign = if self.minutes_left < 0 { "-" } else { "" };
+        let minutes = self.minutes_left.abs();
+        format!("{}{}h{:02}m", sign, minutes / 60, minutes % 60)
+    }
+}
+
+/// The orders already notified by a `check-deadlines --interval` run, and whether they were overdue then
+#[derive(Debug, Default)]
+pub struct NotifiedAlerts(HashMap<String, bool>);
+
+impl NotifiedAlerts {
+    /// The alerts to notify: orders not notified yet, and orders that became overdue since.
+    ///
+    /// Orders no longer at risk are forgotten, they are notified again if they come back.
+    pub fn fresh(&mut self, alerts: &[DeadlineAlert]) -> Vec<DeadlineAlert> {
+        self.0.retain(|order_id, _| alerts.iter().any(|alert| &alert.order_id == order_id));
+        alerts.iter()
+            .filter(|alert| match self.0.insert(alert.order_id.clone(), alert.overdue) {
+                Some(was_overdue) => alert.overdue && !was_overdue,
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeadlineNotification<'a> {
+    /// Number of orders whose deadline already passed
+    pub overdue: usize,
+    /// Number of orders whose deadline is within the window
+    pub due_soon: usize,
+    /// The orders at risk
+    pub alerts: &'a [DeadlineAlert],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn order_due_in(now: DateTime<Utc>, time_left: Duration) -> Order {
+        let mut order = crate::models::orders::fixture_order("shipping-b2b");
+        order.shipping_deadline = Some((now + time_left).to_rfc3339());
+        order
+    }
+
+    #[test]
+    fn orders_are_reported_from_the_window_edge() {
+        let now = DateTime::parse_from_rfc3339("2022-12-01T10:00:00Z").unwrap().with_timezone(&Utc);
+        let check = |time_left| DeadlineAlert::check(&order_due_in(now, time_left), now, 24).unwrap();
+
+        assert!(check(Duration::hours(24) + Duration::minutes(1)).is_none());
+        assert!(check(Duration::hours(24) + Duration::minutes(59)).is_none());
+        let alert = check(Duration::hours(24)).unwrap();
+        assert_eq!((alert.minutes_left, alert.overdue), (24 * 60, false));
+
+        let alert = check(Duration::minutes(59)).unwrap();
+        assert_eq!((alert.hours_left, alert.minutes_left, alert.time_left(), alert.overdue), (0, 59, "0h59m".to_string(), false));
+        assert!(check(Duration::zero()).unwrap().overdue);
+        let alert = check(-Duration::minutes(125)).unwrap();
+        assert_eq!((alert.time_left(), alert.overdue), ("-2h05m".to_string(), true));
+    }
+
+    #[test]
+    fn unreadable_deadlines_are_errors() {
+        let now = Utc::now();
+        let mut order = order_due_in(now, Duration::hours(1));
+        order.shipping_deadline = None;
+        assert!(DeadlineAlert::check(&order, now, 24).unwrap().is_none());
+        order.shipping_deadline = Some("tomorrow".to_string());
+        assert!(DeadlineAlert::check(&order, now, 24).is_err());
+    }
+
+    #[test]
+    fn orders_are_notified_again_only_once_overdue() {
+        let now = Utc::now();
+        let alert = |order_id: &str, time_left| {
+            let mut order = order_due_in(now, time_left);
+            order.order_id = order_id.to_string();
+            DeadlineAlert::check(&order, now, 24).unwrap().unwrap()
+        };
+        let order_ids = |alerts: Vec<DeadlineAlert>| alerts.into_iter().map(|alert| alert.order_id).collect::<Vec<_>>();
+        let mut notified = NotifiedAlerts::default();
+
+        assert_eq!(order_ids(notified.fresh(&[alert("A", Duration::hours(2)), alert("B", -Duration::hours(1))])), vec!["A", "B"]);
+        assert!(notified.fresh(&[alert("A", Duration::hours(1)), alert("B", -Duration::hours(2))]).is_empty());
+        assert_eq!(order_ids(notified.fresh(&[alert("A", -Duration::minutes(1)), alert("C", Duration::hours(3))])), vec!["A", "C"]);
+        // B was shipped in between, it is a new alert when it shows up again
+        assert_eq!(order_ids(notified.fresh(&[alert("A", -Duration::minutes(2)), alert("B", -Duration::hours(3))])), vec!["B"]);
+    }
+}
@@ -0,0 +1,24 @@
+use serde::{de::DeserializeOwned, Deserialize};
+
+use super::unknown::{strip_unknown_fields, UnknownFields};
+
+#[derive(Debug, Deserialize)]
+pub struct ImportResponse {
+    /// The identifier of the import
+    pub import_id: u64,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+strip_unknown_fields! {
+    ImportResponse {},
+}
+
+/// Status of an import, checked until the import is finished
+pub trait ImportStatus: DeserializeOwned {
+    /// Name of the status, e.g. `RUNNING`
+    fn status(&self) -> &str;
+
+    fn is_finished(&self) -> bool;
+}
@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+
+use super::{orders::Order, unknown::{strip_unknown_fields, UnknownFields}};
+
+#[derive(Debug, Serialize)]
+pub struct IncidentRequest {
+    /// The code of the reason used to open or close the incident
+    pub reason_code: String,
+}
+
+/// What is done to the incident of an order line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncidentAction {
+    Open,
+    Close,
+}
+
+impl IncidentAction {
+    /// Type of the reasons the action takes
+    pub fn reason_type(&self) -> &'static str {
+        match self {
+            IncidentAction::Open => "INCIDENT_OPEN",
+            IncidentAction::Close => "INCIDENT_CLOSE",
+        }
+    }
+
+    /// Endpoint of the action, under the order line
+    pub fn endpoint(&self) -> &'static str {
+        match self {
+            IncidentAction::Open => "open_incident",
+            IncidentAction::Close => "close_incident",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReasonResponse {
+    /// List of reasons
+    pub reasons: Vec<Reason>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Reason {
+    /// Reason's code
+    pub code: String,
+    /// Reason's label
+    pub label: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+strip_unknown_fields! {
+    Reason {},
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Serialize)]
+pub struct OrderIncidents {
+    /// Order's identifier
+    pub order_id: String,
+    /// Order's state
+    pub order_state: String,
+    /// Order lines with an open incident
+    pub incidents: Vec<Incident>,
+    /// Fields of the order not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Serialize)]
+pub struct Incident {
+    /// Order line's identifier
+    pub order_line_id: String,
+    /// Associated offer sku
+    pub offer_sku: String,
+    /// Product's quantity for the order line
+    pub quantity: u32,
+    /// Order line's state
+    pub order_line_state: String,
+    /// Reason's code of the incident
+    pub reason_code: Option<String>,
+    /// Reason's label of the incident
+    pub reason_label: Option<String>,
+    /// Fields of the order line not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+impl OrderIncidents {
+    /// Collects the order lines of `order` that are in the `INCIDENT_OPEN` state.
+    ///
+    /// Reason labels missing from the order line are looked up in `reasons`.
+    pub fn from_order(order: &Order, reasons: &[Reason]) -> Self {
+        let incidents = order.order_lines.iter()
+            .filter(|line| line.order_line_state == "INCIDENT_OPEN")
+            .map(|line| {
+                let reason_label = line.order_line_state_reason_label.clone().or_else(|| {
+                    line.order_line_state_reason_code.as_ref().and_then(|code| {
+                        reasons.iter().find(|r| &r.code == code).map(|r| r.label.clone())
+                    })
+                });
+                Incident {
+                    order_line_id: line.order_line_id.clone(),
+                    offer_sku: line.offer_sku.clone(),
+                    quantity: line.quantity,
+                    order_line_state: line.order_line_state.clone(),
+                    reason_code: line.order_line_state_reason_code.clone(),
+                    reason_label,
+                    extra: line.extra.clone(),
+                }
+            })
+            .collect();
+
+        Self {
+            order_id: order.order_id.clone(),
+            order_state: order.order_state.clone(),
+            incidents,
+            extra: order.extra.clone(),
+        }
+    }
+}
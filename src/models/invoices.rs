@@ -1,5 +1,7 @@
 use serde::{Serialize, Deserialize};
 
+use super::unknown::{strip_unknown_fields, UnknownFields};
+
 #[derive(Debug, Serialize)]
 pub struct OrderDocuments {
     pub order_documents: Vec<OrderDocument>,
@@ -27,4 +29,36 @@ pub struct OrderDocumentError {
     pub code: String,
     pub message: String,
     pub field: String,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OrderDocumentListResponse {
+    /// List of documents
+    pub order_documents: Vec<OrderDocumentInfo>,
+    /// Total count of documents
+    pub total_count: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OrderDocumentInfo {
+    /// Document's identifier
+    pub id: u64,
+    /// Document's file name
+    pub file_name: String,
+    /// Document's file size, in bytes
+    pub file_size: Option<u64>,
+    /// Document's type code
+    #[serde(rename = "type")]
+    pub type_code: String,
+    /// Identifier of the order the document is attached to
+    pub order_id: String,
+    /// Document's upload date
+    pub date_uploaded: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+strip_unknown_fields! {
+    OrderDocumentInfo {},
+}
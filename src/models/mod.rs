@@ -1,3 +1,15 @@
 pub mod orders;
 pub mod tracking;
-pub mod invoices;
\ No newline at end of file
+pub mod invoices;
+pub mod incidents;
+pub mod imports;
+pub mod offers;
+pub mod products;
+pub mod transactions;
+pub mod reconciliation;
+pub mod returns;
+pub mod deadlines;
+pub mod tolerant;
+pub mod unknown;
+pub mod additional_fields;
+pub mod api_errors;
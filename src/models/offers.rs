@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::OfferSettings;
+
+use super::{imports::ImportStatus, unknown::{strip_unknown_fields, UnknownFields}};
+
+#[derive(Debug, Deserialize)]
+pub struct StockRecord {
+    /// The offer sku (this is the sku of the offer defined by the shop)
+    pub sku: String,
+    /// Quantity in stock
+    pub quantity: u32,
+    /// Offer's price
+    pub price: f64,
+    /// Identifier of the product the offer is attached to. Defaults to the sku.
+    pub product_id: Option<String>,
+    /// Type of the product identifier (SHOP_SKU, EAN...). Defaults to the configured type.
+    pub product_id_type: Option<String>,
+    /// Offer's state code. Defaults to the configured state.
+    pub state: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct XmlStockUpdate {
+    /// The offers to update
+    #[serde(rename = "offer", default)]
+    pub offers: Vec<StockRecord>,
+}
+
+/// A line of the offers import file, as expected by the marketplace.
+#[derive(Debug, Serialize)]
+pub struct OfferImportLine {
+    /// The offer sku
+    pub sku: String,
+    /// Identifier of the product the offer is attached to
+    #[serde(rename = "product-id")]
+    pub product_id: String,
+    /// Type of the product identifier
+    #[serde(rename = "product-id-type")]
+    pub product_id_type: String,
+    /// Offer's price
+    pub price: f64,
+    /// Quantity in stock
+    pub quantity: u32,
+    /// Offer's state code
+    pub state: String,
+    /// Whether the offer is updated or deleted (`update` or `delete`)
+    #[serde(rename = "update-delete")]
+    pub update_delete: String,
+}
+
+impl OfferImportLine {
+    pub fn from_stock(record: StockRecord, settings: &OfferSettings) -> Self {
+        Self {
+            product_id: record.product_id.unwrap_or_else(|| record.sku.clone()),
+            product_id_type: record.product_id_type.unwrap_or_else(|| settings.product_id_type.clone()),
+            state: record.state.unwrap_or_else(|| settings.default_state_code.clone()),
+            sku: record.sku,
+            price: record.price,
+            quantity: record.quantity,
+            update_delete: "update".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OfferImportStatus {
+    /// Import's status
+    pub status: String,
+    /// Reason of the import's status, if it failed
+    pub reason_status: Option<String>,
+    /// Whether an error report is available for the import
+    pub has_error_report: Option<bool>,
+    /// Number of lines read
+    pub lines_read: Option<u32>,
+    /// Number of lines imported successfully
+    pub lines_in_success: Option<u32>,
+    /// Number of lines rejected
+    pub lines_in_error: Option<u32>,
+    /// Number of offers created
+    pub offer_inserted: Option<u32>,
+    /// Number of offers updated
+    pub offer_updated: Option<u32>,
+    /// Number of offers deleted
+    pub offer_deleted: Option<u32>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+strip_unknown_fields! {
+    OfferImportStatus {},
+}
+
+impl ImportStatus for OfferImportStatus {
+    fn status(&self) -> &str {
+        &self.status
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self.status.as_str(), "COMPLETE" | "FAILED")
+    }
+}
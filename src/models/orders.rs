@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::unknown::{strip_unknown_fields, UnknownFields};
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct OrderResponse {
     /// List of orders
@@ -112,6 +114,9 @@ pub struct Order {
     pub transaction_date: Option<String>,
     /// Payment's transaction number
     pub transaction_number: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -121,6 +126,9 @@ pub struct Channel {
     pub code: String,
     /// Channel label
     pub label: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -146,6 +154,9 @@ pub struct Customer {
     pub organization: Option<Organization>,
     /// Customer's Shipping address
     pub shipping_address: Address,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -161,6 +172,9 @@ pub struct Contact {
     pub lastname: String,
     /// Customer contact's locale
     pub locale: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -190,6 +204,9 @@ pub struct Address {
     pub street_2: Option<String>,
     /// Address zip code
     pub zip_code: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -207,6 +224,9 @@ pub struct Organization {
     pub organization_id: String,
     /// Tax identification number of the organization.
     pub tax_identification_number: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -238,6 +258,9 @@ pub struct OrganizationAddress {
     /// * Egypt
     /// * United Arab Emirates
     pub zip_code: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -247,6 +270,9 @@ pub struct DeliveryDate {
     pub earliest: String,
     /// Latest order delivery date
     pub latest: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -254,6 +280,9 @@ pub struct DeliveryDate {
 pub struct Fulfillment {
     /// Fulfillment center code
     pub center: FulfillmentCenter,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -261,6 +290,9 @@ pub struct Fulfillment {
 pub struct FulfillmentCenter {
     /// Center code
     pub code: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -270,6 +302,9 @@ pub struct InvoiceDetails {
     pub document_details: Option<Vec<DocumentDetail>>,
     /// Payment terms
     pub payment_terms: Option<PaymentTerms>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -277,6 +312,9 @@ pub struct InvoiceDetails {
 pub struct DocumentDetail {
     /// Accounting document format
     pub format: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -285,19 +323,33 @@ pub struct PaymentTerms {
     /// Number of days
     pub days: u32,
     /// Payment terms type
+    // read from `type`, still exported as `type_`
+    #[serde(alias = "type")]
     pub type_: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE", tag = "type")]
 pub enum AdditionalField {
-    Boolean { code: String, value: String },
+    Boolean {
+        code: String,
+        #[serde(serialize_with = "super::additional_fields::serialize_boolean", deserialize_with = "super::additional_fields::deserialize_text")]
+        value: String,
+    },
     Date { code: String, value: String },
     Link { code: String, value: String },
     List { code: String, value: String },
     MultipleValuesList { code: String, value: Vec<String> },
-    Numeric { code: String, value: String },
+    Numeric {
+        code: String,
+        // written as received, a number would lose zeros like in `2.50`
+        #[serde(deserialize_with = "super::additional_fields::deserialize_text")]
+        value: String,
+    },
     Regex { code: String, value: String },
     String { code: String, value: String },
     Textarea { code: String, value: String },
@@ -388,6 +440,9 @@ pub struct OrderLine {
     pub total_commission: f64,
     /// Order line's price with shipping price.
     pub total_price: f64,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -421,12 +476,18 @@ pub struct Cancelation {
     pub shipping_taxes: Option<Vec<Tax>>,
     /// The taxes on the price
     pub taxes: Option<Vec<Tax>>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
 #[derive(Debug, Deserialize, Serialize)]
 pub struct PriceAmountBreakdown {
     pub parts: Vec<PriceAmountBreakdownPart>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -444,6 +505,9 @@ pub struct PriceAmountBreakdownPart {
     // If true, the amount is taken into account when generating the shop payment voucher.
     // If false, the amount is not taken into account when generating the shop payment voucher.
     pub payable_to_shop: Option<bool>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -453,6 +517,9 @@ pub struct CommissionTax {
     pub amount: f64,
     ///  Tax code
     pub code: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -466,6 +533,9 @@ pub struct PurchaseInformation {
     pub purchase_price: f64,
     /// Cancelation's purchase shipping charges
     pub purchase_shipping_price: f64,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -481,6 +551,9 @@ pub struct Tax {
     pub purchase_tax: Option<PurchaseTax>,
     /// Tax rate
     pub rate: Option<f64>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -490,6 +563,9 @@ pub struct PurchaseTax {
     pub purchase_amount: f64,
     /// Purchase tax rate
     pub purchase_rate: Option<f64>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -497,6 +573,9 @@ pub struct PurchaseTax {
 pub struct ShippingFrom {
     /// Describe the address from where the offer is shipped
     pub address: Option<ShippingFromAddress>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -514,6 +593,9 @@ pub struct ShippingFromAddress {
     pub street_2: Option<String>,
     /// Address zip code
     pub zip_code: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -553,6 +635,9 @@ pub struct Refund {
     pub transaction_date: String,
     /// The transaction number of the refund payment
     pub transaction_number: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -569,6 +654,9 @@ pub struct Promotion {
     /// The quantity of free items offered by the promotion for this line.
     /// Only applicable when promotion is of type FREE_ITEMS, null otherwise.
     pub offered_quantity: Option<u32>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -587,6 +675,9 @@ pub struct PromotionConfiguration {
     pub percentage_off: Option<f64>,
     /// Promotion's type, whether it is applied to this item (ITEM), or every items in the basket for this shop (BASKET)
     pub promotion_type: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -597,7 +688,12 @@ pub struct ProductMedia {
     /// Media MIME Type (.jpg, .png ...)
     pub mime_type: String,
     /// Media TYPE (small, large...)
+    // read from `type`, still exported as `type_`
+    #[serde(alias = "type")]
     pub type_: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -611,6 +707,9 @@ pub struct Measurement {
     pub measurement_unit: Option<String>,
     /// Total measurement ordered on the order line
     pub ordered_measurement: Option<f64>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -620,6 +719,9 @@ pub struct OrderReferences {
     pub order_reference_for_customer: Option<String>,
     /// Order reference for seller
     pub order_reference_for_seller: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
 }
 
 #[skip_serializing_none]
@@ -629,4 +731,216 @@ pub struct OrderPromotions {
     pub applied_promotions: Vec<Promotion>,
     /// The total amount deduced on this order thanks to the promotions
     pub total_deduced_amount: f64,
-}
\ No newline at end of file
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+impl Order {
+    /// Sum of the refunded amounts (price and shipping) over all order lines
+    pub fn refunded_amount(&self) -> f64 {
+        self.order_lines.iter()
+            .flat_map(|line| &line.refunds)
+            .map(|refund| refund.amount.unwrap_or_default() + refund.shipping_amount.unwrap_or_default())
+            .sum()
+    }
+
+    /// Sum of the canceled amounts (price and shipping) over all order lines
+    pub fn canceled_amount(&self) -> f64 {
+        self.order_lines.iter()
+            .flat_map(|line| &line.cancelations)
+            .map(|cancelation| cancelation.amount.unwrap_or_default() + cancelation.shipping_amount.unwrap_or_default())
+            .sum()
+    }
+
+    /// Total price of the order, net of refunds and cancellations
+    pub fn net_amount(&self) -> f64 {
+        self.total_price - self.refunded_amount() - self.canceled_amount()
+    }
+
+    /// Total commission of the order, net of the commissions given back on refunds and cancellations
+    pub fn net_commission(&self) -> f64 {
+        let refunded: f64 = self.order_lines.iter()
+            .flat_map(|line| &line.refunds)
+            .map(|refund| refund.commission_total_amount)
+            .sum();
+        let canceled: f64 = self.order_lines.iter()
+            .flat_map(|line| &line.cancelations)
+            .map(|cancelation| cancelation.commission_total_amount)
+            .sum();
+        self.total_commission - refunded - canceled
+    }
+}
+
+strip_unknown_fields! {
+    Order { channel, customer, delivery_date, fulfillment, invoice_details, order_lines, promotions, references, shipping_from },
+    Channel {},
+    Customer { accounting_contact, billing_address, delivery_contact, organization, shipping_address },
+    Contact {},
+    Address {},
+    Organization { address },
+    OrganizationAddress {},
+    DeliveryDate {},
+    Fulfillment { center },
+    FulfillmentCenter {},
+    InvoiceDetails { document_details, payment_terms },
+    DocumentDetail {},
+    PaymentTerms {},
+    OrderLine { cancelations, commission_taxes, measurement, price_amount_breakdown, product_medias, promotions, purchase_information, refunds, shipping_from, shipping_price_amount_breakdown, shipping_taxes, taxes },
+    Cancelation { amount_breakdown, commission_taxes, purchase_information, shipping_amount_breakdown, shipping_taxes, taxes },
+    PriceAmountBreakdown { parts },
+    PriceAmountBreakdownPart {},
+    CommissionTax {},
+    PurchaseInformation {},
+    Tax { amount_breakdown, purchase_tax },
+    PurchaseTax {},
+    ShippingFrom { address },
+    ShippingFromAddress {},
+    Refund { amount_breakdown, commission_taxes, purchase_information, shipping_amount_breakdown, shipping_taxes, taxes },
+    Promotion { configuration },
+    PromotionConfiguration {},
+    ProductMedia {},
+    Measurement {},
+    OrderReferences {},
+    OrderPromotions { applied_promotions },
+}
+
+#[derive(Debug, Serialize)]
+pub struct AcceptanceRequest {
+    /// The acceptance decision for each order line
+    pub order_lines: Vec<OrderLineAcceptance>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderLineAcceptance {
+    /// Whether the order line is accepted
+    pub accepted: bool,
+    /// Order line's identifier
+    pub id: String,
+}
+
+impl AcceptanceRequest {
+    /// Accepts or refuses every line of `order`
+    pub fn for_order(order: &Order, accepted: bool) -> Self {
+        Self {
+            order_lines: order.order_lines.iter()
+                .map(|line| OrderLineAcceptance { accepted, id: line.order_line_id.clone() })
+                .collect(),
+        }
+    }
+}
+
+/// An order recorded from the API, by the name of its file in `fixtures/orders`
+#[cfg(test)]
+pub(crate) fn fixture_order(name: &str) -> Order {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/orders").join(format!("{}.json", name));
+    serde_json::from_reader(std::fs::File::open(path).unwrap()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::unknown::StripUnknownFields;
+    use serde_json::Value;
+    use std::path::PathBuf;
+
+    /// Orders recorded from the API, one order or one orders response per file
+    fn fixture_orders() -> Vec<(PathBuf, Value)> {
+        let folder = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures/orders");
+        let mut paths = std::fs::read_dir(&folder).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension() == Some("json".as_ref()))
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert!(!paths.is_empty(), "no fixtures in {}", folder.display());
+
+        let mut orders = vec![];
+        for path in paths {
+            let fixture: Value = serde_json::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+            match fixture.get("orders").and_then(Value::as_array) {
+                Some(fixture_orders) => orders.extend(fixture_orders.iter().map(|order| (path.clone(), order.clone()))),
+                None => orders.push((path, fixture)),
+            }
+        }
+        orders
+    }
+
+    #[test]
+    fn fixtures_deserialize() {
+        for (path, fixture) in fixture_orders() {
+            let order: Order = serde_path_to_error::deserialize(&fixture)
+                .unwrap_or_else(|e| panic!("{}: {} at {}", path.display(), e.inner(), e.path()));
+            assert_eq!(Some(order.order_id.as_str()), fixture["order_id"].as_str());
+            assert_eq!(order.order_lines.len(), fixture["order_lines"].as_array().unwrap().len());
+        }
+    }
+
+    #[test]
+    fn fixtures_round_trip_to_json() {
+        for (path, fixture) in fixture_orders() {
+            let order: Order = serde_json::from_value(fixture).unwrap();
+            let json = serde_json::to_value(&order).unwrap();
+            let reparsed: Order = serde_json::from_value(json.clone())
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            assert_eq!(serde_json::to_value(&reparsed).unwrap(), json, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn fixtures_serialize_to_xml() {
+        for (path, fixture) in fixture_orders() {
+            let order: Order = serde_json::from_value(fixture).unwrap();
+            let xml = crate::models::unknown::to_xml("Order", &order)
+                .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+            assert!(xml.starts_with("<Order>"), "{}: {}", path.display(), xml);
+            assert!(xml.contains(&format!("<order_id>{}</order_id>", order.order_id)), "{}", path.display());
+            for line in &order.order_lines {
+                assert!(xml.contains(&format!("<order_line_id>{}</order_line_id>", line.order_line_id)), "{}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn type_fields_keep_their_export_element() {
+        let (_, fixture) = fixture_orders().into_iter()
+            .find(|(_, order)| order["order_id"] == "1002-A")
+            .unwrap();
+        let order: Order = serde_json::from_value(fixture).unwrap();
+        let terms = order.invoice_details.as_ref().and_then(|details| details.payment_terms.as_ref());
+        assert_eq!(terms.map(|terms| terms.type_.as_str()), Some("END_OF_MONTH"));
+
+        let xml = crate::models::unknown::to_xml("Order", &order).unwrap();
+        assert!(xml.contains("<type_>END_OF_MONTH</type_>"), "{}", xml);
+        assert!(xml.contains("<type_>SMALL</type_>"), "{}", xml);
+    }
+
+    #[test]
+    fn unknown_fields_are_kept_or_stripped() {
+        let (_, fixture) = fixture_orders().into_iter()
+            .find(|(_, order)| order["order_id"] == "1002-A")
+            .unwrap();
+        let mut order: Order = serde_json::from_value(fixture).unwrap();
+        assert_eq!(order.extra["operator_loyalty_tier"], "GOLD");
+        assert_eq!(order.order_lines[0].extra["warehouse_slot"]["shelf"], 4);
+
+        let xml = crate::models::unknown::to_xml("Order", &order).unwrap();
+        assert!(xml.contains("<operator_loyalty_tier>GOLD</operator_loyalty_tier>"), "{}", xml);
+        assert!(xml.contains("<warehouse_slot><aisle>B</aisle><shelf>4</shelf></warehouse_slot>"), "{}", xml);
+
+        order.strip_unknown_fields();
+        let json = serde_json::to_value(&order).unwrap();
+        assert!(json.get("operator_loyalty_tier").is_none());
+        assert!(json["order_lines"][0].get("warehouse_slot").is_none());
+    }
+
+    #[test]
+    fn amounts_net_of_refunds_and_cancelations() {
+        let (_, fixture) = fixture_orders().into_iter()
+            .find(|(_, order)| order["order_id"] == "1002-A")
+            .unwrap();
+        let order: Order = serde_json::from_value(fixture).unwrap();
+        assert_eq!(order.refunded_amount(), 20.0);
+        assert_eq!(order.canceled_amount(), 55.0);
+        assert_eq!(order.net_amount(), 45.0);
+    }
+}
@@ -0,0 +1,64 @@
+use serde::Deserialize;
+
+use super::{imports::ImportStatus, unknown::{strip_unknown_fields, UnknownFields}};
+
+#[derive(Debug, Deserialize)]
+pub struct ProductImportStatus {
+    /// Import's status
+    pub import_status: String,
+    /// Reason of the import's status, if it failed
+    pub reason_status: Option<String>,
+    /// Whether an error report is available for the import
+    pub has_error_report: Option<bool>,
+    /// Whether a new product report is available for the import
+    pub has_new_product_report: Option<bool>,
+    /// Whether a transformed file is available for the import
+    pub has_transformed_file: Option<bool>,
+    /// Whether a transformation error report is available for the import
+    pub has_transformation_error_report: Option<bool>,
+    /// Number of lines read during the transformation
+    pub transform_lines_read: Option<u32>,
+    /// Number of lines transformed successfully
+    pub transform_lines_in_success: Option<u32>,
+    /// Number of lines rejected during the transformation
+    pub transform_lines_in_error: Option<u32>,
+    /// Number of lines transformed with warnings
+    pub transform_lines_with_warning: Option<u32>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+strip_unknown_fields! {
+    ProductImportStatus {},
+}
+
+impl ImportStatus for ProductImportStatus {
+    fn status(&self) -> &str {
+        &self.import_status
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self.import_status.as_str(), "COMPLETE" | "FAILED" | "CANCELLED" | "REJECTED")
+    }
+}
+
+impl ProductImportStatus {
+    /// The reports available for download, as (endpoint, file suffix) pairs.
+    pub fn available_reports(&self) -> Vec<(&'static str, &'static str)> {
+        let mut reports = vec![];
+        if self.has_error_report.unwrap_or_default() {
+            reports.push(("error_report", "ErrorReport"));
+        }
+        if self.has_new_product_report.unwrap_or_default() {
+            reports.push(("new_product_report", "NewProductReport"));
+        }
+        if self.has_transformed_file.unwrap_or_default() {
+            reports.push(("transformed_file", "TransformedFile"));
+        }
+        if self.has_transformation_error_report.unwrap_or_default() {
+            reports.push(("transformation_error_report", "TransformationErrorReport"));
+        }
+        reports
+    }
+}
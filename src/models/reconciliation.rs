@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+use super::orders::Order;
+
+/// A line of the ERP export of invoiced amounts.
+#[derive(Debug, Deserialize)]
+pub struct ErpInvoiceRecord {
+    /// Order's identifier on the marketplace
+    pub order_id: String,
+    /// Amount invoiced in the ERP
+    pub invoiced_amount: f64,
+    /// Commission booked in the ERP, not compared when the export has no such column
+    #[serde(default)]
+    pub commission: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ReconciliationStatus {
+    Match,
+    /// The invoiced amounts differ
+    Mismatch,
+    /// The invoiced amounts match, the commissions differ
+    CommissionMismatch,
+    MissingInMarketplace,
+    /// The order is on the marketplace, in the period of the ERP export, but not in it
+    MissingInErp,
+}
+
+/// A line of the reconciliation report.
+#[derive(Debug, Serialize)]
+pub struct ReconciliationRecord {
+    pub order_id: String,
+    pub status: ReconciliationStatus,
+    pub erp_amount: Option<f64>,
+    pub marketplace_amount: Option<f64>,
+    pub refunded_amount: Option<f64>,
+    pub canceled_amount: Option<f64>,
+    pub erp_commission: Option<f64>,
+    pub commission: Option<f64>,
+    pub payout: Option<f64>,
+    pub difference: Option<f64>,
+    pub commission_difference: Option<f64>,
+}
+
+impl ReconciliationRecord {
+    pub fn compare(erp: &ErpInvoiceRecord, order: Option<&Order>, tolerance: f64) -> Self {
+        match order {
+            Some(order) => {
+                let mut record = Self::from_order(order);
+                let difference = erp.invoiced_amount - record.marketplace_amount.unwrap_or_default();
+                let commission_difference = erp.commission.map(|commission| commission - record.commission.unwrap_or_default());
+                record.status = if difference.abs() > tolerance {
+                    ReconciliationStatus::Mismatch
+                } else if matches!(commission_difference, Some(difference) if difference.abs() > tolerance) {
+                    ReconciliationStatus::CommissionMismatch
+                } else {
+                    ReconciliationStatus::Match
+                };
+                record.erp_amount = Some(erp.invoiced_amount);
+                record.erp_commission = erp.commission;
+                record.difference = Some(difference);
+                record.commission_difference = commission_difference;
+                record
+            },
+            None => Self {
+                order_id: erp.order_id.clone(),
+                status: ReconciliationStatus::MissingInMarketplace,
+                erp_amount: Some(erp.invoiced_amount),
+                marketplace_amount: None,
+                refunded_amount: None,
+                canceled_amount: None,
+                erp_commission: erp.commission,
+                commission: None,
+                payout: None,
+                difference: None,
+                commission_difference: None,
+            },
+        }
+    }
+
+    /// The record of an order the ERP export does not have
+    pub fn missing_in_erp(order: &Order) -> Self {
+        Self {
+            status: ReconciliationStatus::MissingInErp,
+            ..Self::from_order(order)
+        }
+    }
+
+    fn from_order(order: &Order) -> Self {
+        let marketplace_amount = order.net_amount();
+        let commission = order.net_commission();
+        Self {
+            order_id: order.order_id.clone(),
+            status: ReconciliationStatus::Match,
+            erp_amount: None,
+            marketplace_amount: Some(marketplace_amount),
+            refunded_amount: Some(order.refunded_amount()),
+            canceled_amount: Some(order.canceled_amount()),
+            erp_commission: None,
+            commission: Some(commission),
+            payout: Some(marketplace_amount - commission),
+            difference: None,
+            commission_difference: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order() -> Order {
+        crate::models::orders::fixture_order("shipping-b2b")
+    }
+
+    fn erp(invoiced_amount: f64, commission: Option<f64>) -> ErpInvoiceRecord {
+        ErpInvoiceRecord { order_id: order().order_id, invoiced_amount, commission }
+    }
+
+    #[test]
+    fn commission_column_is_optional() {
+        for csv in ["order_id,invoiced_amount\n1001-A,65.0\n", "order_id,invoiced_amount,commission\n1001-A,65.0,\n"] {
+            let records = csv::Reader::from_reader(csv.as_bytes())
+                .into_deserialize()
+                .collect::<Result<Vec<ErpInvoiceRecord>, _>>()
+                .unwrap();
+            assert_eq!((records[0].invoiced_amount, records[0].commission), (65.0, None));
+        }
+    }
+
+    #[test]
+    fn amounts_then_commissions_are_compared() {
+        let order = order();
+        let (amount, commission) = (order.net_amount(), order.net_commission());
+
+        assert_eq!(ReconciliationRecord::compare(&erp(amount, None), Some(&order), 0.01).status, ReconciliationStatus::Match);
+        assert_eq!(ReconciliationRecord::compare(&erp(amount, Some(commission)), Some(&order), 0.01).status, ReconciliationStatus::Match);
+        assert_eq!(ReconciliationRecord::compare(&erp(amount + 1.0, Some(commission)), Some(&order), 0.01).status, ReconciliationStatus::Mismatch);
+
+        let record = ReconciliationRecord::compare(&erp(amount, Some(commission - 0.5)), Some(&order), 0.01);
+        assert_eq!(record.status, ReconciliationStatus::CommissionMismatch);
+        assert_eq!(record.commission_difference, Some(-0.5));
+
+        assert_eq!(ReconciliationRecord::compare(&erp(amount, None), None, 0.01).status, ReconciliationStatus::MissingInMarketplace);
+        let record = ReconciliationRecord::missing_in_erp(&order);
+        assert_eq!((record.status, record.erp_amount, record.marketplace_amount), (ReconciliationStatus::MissingInErp, None, Some(amount)));
+    }
+}
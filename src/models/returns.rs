@@ -0,0 +1,209 @@
+use std::collections::{hash_map::Entry, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use super::unknown::{strip_unknown_fields, UnknownFields};
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReturnResponse {
+    /// List of returns
+    pub data: Vec<Return>,
+    /// Token of the next page, if there is one
+    pub next_page_token: Option<String>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Return {
+    /// Return's identifier
+    pub id: String,
+    /// Identifier of the returned order
+    pub order_id: String,
+    /// Commercial identifier of the returned order
+    pub order_commercial_id: Option<String>,
+    /// Return's state
+    pub state: String,
+    /// Return's creation date
+    pub date_created: String,
+    /// Return's last updated date
+    pub last_updated: Option<String>,
+    /// Return's reason code
+    pub reason_code: Option<String>,
+    /// Return's tracking information
+    pub tracking: Option<ReturnTracking>,
+    /// Returned lines
+    pub return_lines: Vec<ReturnLine>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReturnTracking {
+    /// Carrier's name
+    pub carrier_name: Option<String>,
+    /// Carrier tracking number
+    pub tracking_number: Option<String>,
+    /// Carrier tracking url
+    pub tracking_url: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReturnLine {
+    /// Return line's identifier
+    pub id: String,
+    /// Identifier of the returned order line
+    pub order_line_id: String,
+    /// Sku of the returned offer
+    pub offer_sku: Option<String>,
+    /// Returned quantity
+    pub quantity: u32,
+    /// Return line's reason code
+    pub reason_code: Option<String>,
+    /// Return line's state
+    pub state: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+strip_unknown_fields! {
+    Return { tracking, return_lines },
+    ReturnTracking {},
+    ReturnLine {},
+}
+
+/// The status a return can be moved to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReturnStatus {
+    Received,
+    Accepted,
+    Refused,
+}
+
+impl ReturnStatus {
+    /// The endpoint action to call for the status.
+    pub fn action(&self) -> &'static str {
+        match self {
+            ReturnStatus::Received => "receive",
+            ReturnStatus::Accepted => "accept",
+            ReturnStatus::Refused => "refuse",
+        }
+    }
+}
+
+/// A line of the return updates file sent by the warehouse.
+#[derive(Debug, Deserialize)]
+pub struct ReturnUpdateRecord {
+    /// Return's identifier
+    pub return_id: String,
+    /// Return line's identifier
+    pub return_line_id: String,
+    /// New status of the return line
+    pub status: ReturnStatus,
+    /// Quantity concerned by the update
+    pub quantity: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReturnUpdateRequest {
+    /// Updated return lines
+    pub return_lines: Vec<ReturnLineUpdate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReturnLineUpdate {
+    /// Return line's identifier
+    pub id: String,
+    /// Quantity concerned by the update
+    pub quantity: u32,
+}
+
+/// The lines of a return moved to the same status, sent in one call
+#[derive(Debug)]
+pub struct ReturnUpdate {
+    pub return_id: String,
+    pub status: ReturnStatus,
+    pub request: ReturnUpdateRequest,
+}
+
+impl ReturnUpdate {
+    /// Groups the lines of the updates file by return and status, keeping the order of the file
+    pub fn group(records: Vec<ReturnUpdateRecord>) -> Vec<ReturnUpdate> {
+        let mut updates: Vec<ReturnUpdate> = vec![];
+        let mut positions: HashMap<(String, ReturnStatus), usize> = HashMap::new();
+        for record in records {
+            let line = ReturnLineUpdate { id: record.return_line_id, quantity: record.quantity };
+            match positions.entry((record.return_id.clone(), record.status)) {
+                Entry::Occupied(position) => updates[*position.get()].request.return_lines.push(line),
+                Entry::Vacant(position) => {
+                    position.insert(updates.len());
+                    updates.push(ReturnUpdate {
+                        return_id: record.return_id,
+                        status: record.status,
+                        request: ReturnUpdateRequest { return_lines: vec![line] },
+                    });
+                },
+            }
+        }
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::unknown::to_xml;
+
+    #[test]
+    fn updates_are_grouped_by_return_and_status() {
+        let file = "return_id,return_line_id,status,quantity\n\
+            R2,R2-1,received,1\n\
+            R1,R1-1,accepted,2\n\
+            R2,R2-2,received,3\n\
+            R2,R2-1,refused,1\n";
+        let records = csv::Reader::from_reader(file.as_bytes())
+            .into_deserialize()
+            .collect::<Result<Vec<ReturnUpdateRecord>, _>>()
+            .unwrap();
+
+        let updates = ReturnUpdate::group(records);
+        let groups = updates.iter()
+            .map(|update| (update.return_id.as_str(), update.status, update.request.return_lines.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(groups, vec![
+            ("R2", ReturnStatus::Received, 2),
+            ("R1", ReturnStatus::Accepted, 1),
+            ("R2", ReturnStatus::Refused, 1),
+        ]);
+        assert_eq!(updates[0].status.action(), "receive");
+        assert_eq!(serde_json::to_value(&updates[0].request).unwrap(), serde_json::json!({
+            "return_lines": [{ "id": "R2-1", "quantity": 1 }, { "id": "R2-2", "quantity": 3 }],
+        }));
+    }
+
+    #[test]
+    fn returns_are_exported_with_their_lines() {
+        let response: ReturnResponse = serde_json::from_value(serde_json::json!({
+            "data": [{
+                "id": "R1",
+                "order_id": "1001-A",
+                "state": "WAITING_RECEPTION",
+                "date_created": "2022-12-01T10:00:00Z",
+                "return_lines": [{ "id": "R1-1", "order_line_id": "1001-A-1", "quantity": 2, "label_url": "https://example.com/label" }],
+                "rma": "RMA-7",
+            }],
+        })).unwrap();
+
+        let xml = to_xml("Return", &response.data[0]).unwrap();
+        assert!(xml.starts_with("<Return><id>R1</id><order_id>1001-A</order_id>"), "{}", xml);
+        assert!(xml.contains("<return_lines><id>R1-1</id><order_line_id>1001-A-1</order_line_id><quantity>2</quantity><label_url>https://example.com/label</label_url></return_lines>"), "{}", xml);
+        assert!(xml.contains("<rma>RMA-7</rma>"), "{}", xml);
+    }
+}
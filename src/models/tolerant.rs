@@ -0,0 +1,172 @@
+//! Lenient deserialization of API payloads.
+//!
+//! Mirakl sometimes sends `null` (or nothing at all) for fields documented as required.
+//! In tolerant mode those values are replaced by the type's default and logged, instead of
+//! failing the whole response.
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use serde_path_to_error::{Path, Segment};
+use tracing::warn;
+
+/// Deserializes `value`, repairing unexpected nulls and missing fields when `tolerant` is set
+///
+/// Serde stops at the first error, so each pass repairs one kind of problem, e.g. a null
+/// `category_label` in order lines, in every order and line at once. The number of passes
+/// depends on how many kinds of problems there are, not on the size of the payload.
+pub fn from_value<T: DeserializeOwned>(mut value: Value, tolerant: bool) -> serde_json::Result<T> {
+    if !tolerant {
+        return serde_json::from_value(value);
+    }
+
+    loop {
+        let error = match serde_path_to_error::deserialize::<_, T>(&value) {
+            Ok(parsed) => return Ok(parsed),
+            Err(error) => error,
+        };
+        let message = error.inner().to_string();
+        let segments = shape(error.path());
+        let repaired = repair(&mut value, &segments, &message);
+        if repaired == 0 {
+            return Err(error.into_inner());
+        }
+        let path = segments.iter()
+            .map(|segment| match segment {
+                Some(Segment::Map { key }) => key.as_str(),
+                Some(Segment::Enum { variant }) => variant.as_str(),
+                Some(_) => "?",
+                None => "[*]",
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+            .replace(".[*]", "[*]");
+        warn!("Unexpected payload at {}: {}, using the default value for {} values", path, message, repaired);
+    }
+}
+
+/// The path to the same field of every item of the lists on the way, `None` standing for every item
+fn shape(path: &Path) -> Vec<Option<&Segment>> {
+    path.iter()
+        .map(|segment| match segment {
+            Segment::Seq { .. } => None,
+            segment => Some(segment),
+        })
+        .collect()
+}
+
+/// Fixes the values pointed to by `segments`, returns the number of values repaired
+fn repair(value: &mut Value, segments: &[Option<&Segment>], message: &str) -> usize {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return repair_value(value, message) as usize,
+    };
+    match (segment, value) {
+        (None, Value::Array(items)) => items.iter_mut().map(|item| repair(item, rest, message)).sum(),
+        (Some(Segment::Map { key }), Value::Object(map)) => match map.get_mut(key) {
+            Some(item) => repair(item, rest, message),
+            None => 0,
+        },
+        (Some(Segment::Enum { .. }), value) => repair(value, rest, message),
+        _ => 0,
+    }
+}
+
+/// Fixes a single value, returns whether it needed and could get a repair
+fn repair_value(target: &mut Value, message: &str) -> bool {
+    if let Some(field) = message.strip_prefix("missing field `").and_then(|rest| rest.split('`').next()) {
+        // the next pass reports the inserted null with the expected type
+        return match target {
+            Value::Object(map) if !map.contains_key(field) => map.insert(field.to_string(), Value::Null).is_none(),
+            _ => false,
+        };
+    }
+
+    let expected = match message.strip_prefix("invalid type: null, expected ") {
+        Some(expected) if target.is_null() => expected,
+        _ => return false,
+    };
+    *target = match expected {
+        "a string" | "a borrowed string" => Value::String(String::new()),
+        "a boolean" => Value::Bool(false),
+        "f32" | "f64" => Value::from(0.0),
+        "i8" | "i16" | "i32" | "i64" | "u8" | "u16" | "u32" | "u64" => Value::from(0),
+        "a sequence" => Value::Array(vec![]),
+        expected if expected.starts_with("struct ") || expected == "a map" => Value::Object(Default::default()),
+        _ => return false,
+    };
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::orders::{fixture_order, Order, OrderResponse};
+
+    fn fixture() -> Value {
+        serde_json::to_value(fixture_order("waiting-acceptance")).unwrap()
+    }
+
+    #[test]
+    fn strict_mode_rejects_nulls() {
+        let mut order = fixture();
+        order["order_lines"][0]["category_label"] = Value::Null;
+        assert!(from_value::<Order>(order, false).is_err());
+    }
+
+    #[test]
+    fn tolerant_mode_defaults_nulls_and_missing_fields() {
+        let mut order = fixture();
+        order["order_lines"][0]["category_label"] = Value::Null;
+        order["order_lines"][0]["price_unit"] = Value::Null;
+        order["order_lines"][0]["cancelations"] = Value::Null;
+        order["can_cancel"] = Value::Null;
+        order.as_object_mut().unwrap().remove("shipping_zone_label");
+        order.as_object_mut().unwrap().remove("fulfillment");
+
+        let order: Order = from_value(order, true).unwrap();
+        assert_eq!(order.order_lines[0].category_label, "");
+        assert_eq!(order.order_lines[0].price_unit, 0.0);
+        assert!(order.order_lines[0].cancelations.is_empty());
+        assert!(!order.can_cancel);
+        assert_eq!(order.shipping_zone_label, "");
+        assert_eq!(order.fulfillment.center.code, "");
+    }
+
+    #[test]
+    fn tolerant_mode_repairs_orders_in_a_response() {
+        let mut order = fixture();
+        order["customer"]["billing_address"] = Value::Null;
+        let response = serde_json::json!({ "orders": [fixture(), order], "total_count": Value::Null });
+
+        let response: OrderResponse = from_value(response, true).unwrap();
+        assert_eq!(response.orders.len(), 2);
+        assert_eq!(response.total_count, 0);
+        assert_eq!(response.orders[1].customer.billing_address.city, "");
+    }
+
+    #[test]
+    fn tolerant_mode_repairs_every_order_at_once() {
+        let mut order = fixture();
+        order["order_lines"][0]["category_label"] = Value::Null;
+        order.as_object_mut().unwrap().remove("shipping_zone_label");
+        let mut valid = fixture();
+        valid["shipping_zone_label"] = Value::from("Europe");
+        let mut response = serde_json::json!({ "orders": [order.clone(), valid, order], "total_count": 3 });
+
+        let error = serde_path_to_error::deserialize::<_, OrderResponse>(&response).unwrap_err();
+        let segments = shape(error.path());
+        assert_eq!(repair(&mut response, &segments, &error.inner().to_string()), 2);
+
+        let response: OrderResponse = from_value(response, true).unwrap();
+        let zones = response.orders.iter().map(|o| o.shipping_zone_label.as_str()).collect::<Vec<_>>();
+        assert_eq!(zones, vec!["", "Europe", ""]);
+        assert_eq!(response.orders[2].order_lines[0].category_label, "");
+    }
+
+    #[test]
+    fn tolerant_mode_still_rejects_wrong_types() {
+        let mut order = fixture();
+        order["order_lines"][0]["quantity"] = Value::from("two");
+        assert!(from_value::<Order>(order, true).is_err());
+    }
+}
@@ -1,5 +1,13 @@
+use std::{ops::Range, path::Path};
+
+use miette::NamedSource;
+use quick_xml::{events::Event, Reader};
 use serde::{Deserialize, Serialize};
 
+use crate::{error::TrackingFileError, prelude::*};
+
+use super::unknown::{strip_unknown_fields, UnknownFields};
+
 #[derive(Debug, Serialize)]
 pub struct TrackingRequest {
     /// The code of a carrier. This information is mandatory for a registered carrier.
@@ -12,8 +20,9 @@ pub struct TrackingRequest {
     pub tracking_number: Option<String>,
 }
 
+/// Tracking info of an order, as read from a tracking file (XML or CSV)
 #[derive(Debug, Deserialize)]
-pub struct XmlTrackingRequest {
+pub struct OrderTracking {
     /// The order id.
     pub order_id: String,
     /// The code of a carrier. This information is mandatory for a registered carrier.
@@ -26,13 +35,335 @@ pub struct XmlTrackingRequest {
     pub tracking_number: Option<String>,
 }
 
-impl From<XmlTrackingRequest> for TrackingRequest {
-    fn from(xml: XmlTrackingRequest) -> Self {
+impl From<OrderTracking> for TrackingRequest {
+    fn from(tracking: OrderTracking) -> Self {
         Self {
-            carrier_code: xml.carrier_code,
-            carrier_name: xml.carrier_name,
-            carrier_url: xml.carrier_url,
-            tracking_number: xml.tracking_number,
+            carrier_code: tracking.carrier_code,
+            carrier_name: tracking.carrier_name,
+            carrier_url: tracking.carrier_url,
+            tracking_number: tracking.tracking_number,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CarrierResponse {
+    /// Carriers registered on the marketplace
+    pub carriers: Vec<Carrier>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Carrier {
+    /// Carrier's code
+    pub code: String,
+    /// Carrier's label
+    pub label: String,
+    /// Tracking url of the carrier, `{trackingId}` stands for the tracking number
+    pub tracking_url: Option<String>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+strip_unknown_fields! {
+    Carrier {},
+}
+
+/// Why a tracking request can't be pushed to the marketplace
+#[derive(Debug)]
+pub struct CarrierProblem {
+    /// Element (or column) at fault, `None` when the request as a whole is
+    pub element: Option<&'static str>,
+    pub message: String,
+    pub help: String,
+}
+
+impl OrderTracking {
+    /// Checks the request against the carriers registered on the marketplace
+    pub fn check_carrier(&self, carriers: &[Carrier]) -> std::result::Result<(), CarrierProblem> {
+        let present = |value: &Option<String>| matches!(value.as_deref(), Some(v) if !v.trim().is_empty());
+        if !present(&self.carrier_code) {
+            if present(&self.carrier_name) {
+                return Ok(());
+            }
+            return Err(CarrierProblem {
+                element: None,
+                message: "an unregistered carrier requires a carrier_name".to_string(),
+                help: "Add a <carrier_code> for a carrier registered on the marketplace, or a <carrier_name> for another one".to_string(),
+            });
+        }
+
+        let code = self.carrier_code.as_deref().unwrap_or_default().trim();
+        let carrier = match carriers.iter().find(|carrier| carrier.code == code) {
+            Some(carrier) => carrier,
+            None => {
+                let codes = carriers.iter().map(|carrier| carrier.code.as_str()).collect::<Vec<_>>().join(", ");
+                return Err(CarrierProblem {
+                    element: Some("carrier_code"),
+                    message: format!("carrier {} is not registered on the marketplace", code),
+                    help: format!("Use one of {}, or remove <carrier_code> and set <carrier_name> for an unregistered carrier", codes),
+                });
+            },
+        };
+        let needs_tracking_number = matches!(&carrier.tracking_url, Some(url) if url.contains("{trackingId}"));
+        if needs_tracking_number && !present(&self.tracking_number) {
+            return Err(CarrierProblem {
+                element: Some("carrier_code"),
+                message: format!("carrier {} requires a tracking_number", carrier.label),
+                help: "Add the <tracking_number> element".to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TrackingStatus {
+    Pushed,
+    /// Valid, but not sent in dry-run mode
+    NotSent,
+    Failed,
+}
+
+/// A line of the result file of a batch tracking push
+#[derive(Debug, Serialize)]
+pub struct TrackingResult {
+    pub order_id: String,
+    /// Line of the record in the tracking file
+    pub line: usize,
+    pub status: TrackingStatus,
+    pub message: String,
+}
+
+/// An XML element and its position in the file
+struct Element {
+    name: String,
+    /// Number of enclosing elements, 0 for the root
+    depth: usize,
+    span: Range<usize>,
+}
+
+/// A tracking file kept in memory with the position of its elements, so errors can point at them.
+pub struct TrackingFile {
+    name: String,
+    content: String,
+    /// Every element, in document order
+    elements: Vec<Element>,
+}
+
+impl TrackingFile {
+    /// Reads the file and checks it is well-formed XML
+    pub fn read(path: &Path) -> Result<Self> {
+        Self::new(path.display().to_string(), std::fs::read_to_string(path)?)
+    }
+
+    /// Checks the content is well-formed XML, `name` identifies it in errors
+    pub fn new(name: String, content: String) -> Result<Self> {
+        let mut file = Self { name, content, elements: vec![] };
+
+        let mut elements = vec![];
+        let mut open: Vec<(String, usize)> = vec![];
+        let mut reader = Reader::from_str(&file.content);
+        loop {
+            let start = reader.buffer_position();
+            match reader.read_event() {
+                Ok(Event::Start(e)) => open.push((String::from_utf8_lossy(e.name().as_ref()).to_string(), start)),
+                Ok(Event::Empty(e)) => elements.push(Element {
+                    name: String::from_utf8_lossy(e.name().as_ref()).to_string(),
+                    depth: open.len(),
+                    span: start..reader.buffer_position(),
+                }),
+                Ok(Event::End(_)) => {
+                    if let Some((name, element_start)) = open.pop() {
+                        elements.push(Element { name, depth: open.len(), span: element_start..reader.buffer_position() });
+                    }
+                },
+                Ok(Event::Eof) => break,
+                Ok(_) => (),
+                Err(e) => {
+                    // point at the whole tag the reader stopped in
+                    let end = file.content[start..].find('>').map_or(file.content.len(), |i| start + i + 1);
+                    return Err(file.error(start..end, format!("malformed XML: {}", e), None));
+                },
+            }
+        }
+        if let Some((name, start)) = open.pop() {
+            let end = start + name.len() + 2;
+            return Err(file.error(start..end, format!("<{}> is never closed", name), Some(format!("Add </{}> after its content", name))));
+        }
+        elements.sort_by_key(|element| element.span.start);
+        file.elements = elements;
+
+        Ok(file)
+    }
+
+    /// The tracking requests of the file: the `<tracking>` children of the root, or the root itself when it has none
+    pub fn records(&self) -> Vec<TrackingRecord<'_>> {
+        let records = self.elements.iter()
+            .filter(|element| element.depth == 1 && element.name == "tracking")
+            .map(|element| TrackingRecord { file: self, span: element.span.clone() })
+            .collect::<Vec<_>>();
+        if !records.is_empty() {
+            return records;
         }
+        vec![TrackingRecord { file: self, span: self.root() }]
+    }
+
+    fn root(&self) -> Range<usize> {
+        self.elements.first().map(|element| element.span.clone()).unwrap_or(0..self.content.len())
     }
-}
\ No newline at end of file
+
+    fn error(&self, span: Range<usize>, label: String, help: Option<String>) -> WunderError {
+        WunderError::InvalidTrackingFile(Box::new(TrackingFileError {
+            file_name: self.name.clone(),
+            source_code: NamedSource::new(&self.name, self.content.clone()),
+            span: span.into(),
+            label,
+            help,
+        }))
+    }
+}
+
+/// One tracking request of a [`TrackingFile`]
+pub struct TrackingRecord<'a> {
+    file: &'a TrackingFile,
+    span: Range<usize>,
+}
+
+impl TrackingRecord<'_> {
+    /// Line the record starts on
+    pub fn line(&self) -> usize {
+        self.file.content[..self.span.start].matches('\n').count() + 1
+    }
+
+    /// Deserializes the tracking request, pointing at the offending element when it fails
+    pub fn parse(&self) -> Result<OrderTracking> {
+        quick_xml::de::from_str(&self.file.content[self.span.clone()]).map_err(|e| {
+            let message = e.to_string();
+            // serde names the field it failed on between backticks
+            let field = message.split('`').nth(1).map(str::to_string);
+            match field.as_deref().and_then(|field| self.element(field)) {
+                Some(span) => self.file.error(span, message, None),
+                None => {
+                    let help = field.map(|field| format!("Add the <{}> element", field));
+                    self.file.error(self.span.clone(), message, help)
+                },
+            }
+        })
+    }
+
+    /// Checks the request against the carriers registered on the marketplace
+    pub fn check_carrier(&self, request: &OrderTracking, carriers: &[Carrier]) -> Result<()> {
+        request.check_carrier(carriers).map_err(|problem| {
+            let span = problem.element.and_then(|element| self.element(element)).unwrap_or_else(|| self.span.clone());
+            self.file.error(span, problem.message, Some(problem.help))
+        })
+    }
+
+    /// Span of the first element of the record with that name
+    fn element(&self, name: &str) -> Option<Range<usize>> {
+        self.file.elements.iter()
+            .find(|element| element.name == name && element.span.start >= self.span.start && element.span.end <= self.span.end)
+            .map(|element| element.span.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn carriers() -> Vec<Carrier> {
+        vec![
+            Carrier { code: "DHL".to_string(), label: "DHL".to_string(), tracking_url: Some("https://dhl.example/{trackingId}".to_string()), extra: UnknownFields::default() },
+            Carrier { code: "PICKUP".to_string(), label: "Store pickup".to_string(), tracking_url: None, extra: UnknownFields::default() },
+        ]
+    }
+
+    fn check(xml: &str) -> Result<OrderTracking> {
+        let file = TrackingFile::new("tracking.xml".to_string(), xml.to_string())?;
+        let records = file.records();
+        assert_eq!(records.len(), 1);
+        let request = records[0].parse()?;
+        records[0].check_carrier(&request, &carriers())?;
+        Ok(request)
+    }
+
+    /// The label and source snippet the error points at
+    fn pointed_at(error: WunderError, xml: &str) -> (String, String) {
+        match error {
+            WunderError::InvalidTrackingFile(error) => {
+                let snippet = xml[error.span.offset()..error.span.offset() + error.span.len()].to_string();
+                (error.label, snippet)
+            },
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn valid_requests() {
+        assert!(check("<tracking><order_id>1</order_id><carrier_code>DHL</carrier_code><tracking_number>42</tracking_number></tracking>").is_ok());
+        assert!(check("<tracking><order_id>1</order_id><carrier_code>PICKUP</carrier_code></tracking>").is_ok());
+        assert!(check("<tracking><order_id>1</order_id><carrier_name>Courier</carrier_name></tracking>").is_ok());
+    }
+
+    #[test]
+    fn malformed_xml_points_at_the_tag() {
+        let xml = "<tracking><order_id>1</order_id></tracking_>";
+        let (label, snippet) = pointed_at(check(xml).unwrap_err(), xml);
+        assert!(label.starts_with("malformed XML"), "{}", label);
+        assert_eq!(snippet, "</tracking_>");
+
+        let xml = "<tracking><order_id>1</order_id>";
+        let (label, snippet) = pointed_at(check(xml).unwrap_err(), xml);
+        assert_eq!(label, "<tracking> is never closed");
+        assert_eq!(snippet, "<tracking>");
+    }
+
+    #[test]
+    fn carrier_checks_point_at_the_element() {
+        let xml = "<tracking><order_id>1</order_id><carrier_code>FEDEX</carrier_code></tracking>";
+        let (label, snippet) = pointed_at(check(xml).unwrap_err(), xml);
+        assert_eq!(label, "carrier FEDEX is not registered on the marketplace");
+        assert_eq!(snippet, "<carrier_code>FEDEX</carrier_code>");
+
+        let xml = "<tracking><order_id>1</order_id><carrier_code>DHL</carrier_code></tracking>";
+        let (label, _) = pointed_at(check(xml).unwrap_err(), xml);
+        assert_eq!(label, "carrier DHL requires a tracking_number");
+
+        let xml = "<tracking><order_id>1</order_id><carrier_code> </carrier_code></tracking>";
+        let (label, snippet) = pointed_at(check(xml).unwrap_err(), xml);
+        assert_eq!(label, "an unregistered carrier requires a carrier_name");
+        assert_eq!(snippet, xml);
+    }
+
+    #[test]
+    fn multi_record_files_are_checked_record_by_record() {
+        let xml = "<trackings>\n\
+            <tracking><order_id>1</order_id><carrier_code>DHL</carrier_code><tracking_number>42</tracking_number></tracking>\n\
+            <tracking><order_id>2</order_id><carrier_code>DHL</carrier_code></tracking>\n\
+            <tracking><carrier_name>Courier</carrier_name></tracking>\n\
+            </trackings>";
+        let file = TrackingFile::new("trackings.xml".to_string(), xml.to_string()).unwrap();
+        let records = file.records();
+        assert_eq!(records.iter().map(TrackingRecord::line).collect::<Vec<_>>(), vec![2, 3, 4]);
+
+        let first = records[0].parse().unwrap();
+        assert!(records[0].check_carrier(&first, &carriers()).is_ok());
+
+        // the carrier_code of the second record is pointed at, not the first one of the file
+        let second = records[1].parse().unwrap();
+        match records[1].check_carrier(&second, &carriers()).unwrap_err() {
+            WunderError::InvalidTrackingFile(error) => {
+                assert_eq!(error.label, "carrier DHL requires a tracking_number");
+                assert_eq!(error.span.offset(), xml.rfind("<carrier_code>DHL</carrier_code>").unwrap());
+            },
+            other => panic!("unexpected error {:?}", other),
+        }
+
+        let (label, snippet) = pointed_at(records[2].parse().unwrap_err(), xml);
+        assert_eq!(label, "missing field `order_id`");
+        assert_eq!(snippet, "<tracking><carrier_name>Courier</carrier_name></tracking>");
+    }
+}
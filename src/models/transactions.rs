@@ -0,0 +1,353 @@
+use chrono::{DateTime, FixedOffset};
+use serde::{Deserialize, Serialize};
+
+use super::unknown::{serialize_as_json, strip_unknown_fields, UnknownFields};
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionLogResponse {
+    /// List of transactions
+    pub data: Vec<TransactionLog>,
+    /// Token of the next page, if there is one
+    pub next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionLog {
+    /// Transaction's identifier
+    pub id: String,
+    /// Transaction's type (ORDER_AMOUNT, COMMISSION_FEE, REFUND_ORDER_AMOUNT...)
+    #[serde(rename = "type")]
+    pub transaction_type: String,
+    /// Transaction's amount
+    pub amount: f64,
+    /// Transaction's currency (iso format)
+    pub currency_iso_code: String,
+    /// Transaction's creation date
+    pub date_created: String,
+    /// Transaction's last updated date
+    pub last_updated: Option<String>,
+    /// Payment state of the transaction
+    pub payment_state: Option<String>,
+    /// Identifier of the accounting document the transaction is part of
+    pub accounting_document_id: Option<String>,
+    /// Number of the accounting document the transaction is part of
+    pub accounting_document_number: Option<String>,
+    /// Entities the transaction relates to
+    pub entities: Option<TransactionEntities>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionEntities {
+    /// Related order
+    pub order: Option<TransactionEntity>,
+    /// Related order line
+    pub order_line: Option<TransactionEntity>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionEntity {
+    /// Entity's identifier
+    pub id: String,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+/// A transaction, flattened for export.
+#[derive(Debug, Serialize)]
+pub struct TransactionRecord {
+    pub id: String,
+    pub transaction_type: String,
+    pub amount: f64,
+    pub currency_iso_code: String,
+    pub date_created: String,
+    pub last_updated: Option<String>,
+    pub payment_state: Option<String>,
+    pub order_id: Option<String>,
+    pub order_line_id: Option<String>,
+    pub accounting_document_id: Option<String>,
+    pub accounting_document_number: Option<String>,
+    /// Fields of the transaction not known by the model, as JSON
+    #[serde(serialize_with = "serialize_as_json")]
+    pub extra: UnknownFields,
+}
+
+impl From<TransactionLog> for TransactionRecord {
+    fn from(log: TransactionLog) -> Self {
+        let (order_id, order_line_id) = match log.entities {
+            Some(entities) => (entities.order.map(|o| o.id), entities.order_line.map(|l| l.id)),
+            None => (None, None),
+        };
+        Self {
+            id: log.id,
+            transaction_type: log.transaction_type,
+            amount: log.amount,
+            currency_iso_code: log.currency_iso_code,
+            date_created: log.date_created,
+            last_updated: log.last_updated,
+            payment_state: log.payment_state,
+            order_id,
+            order_line_id,
+            accounting_document_id: log.accounting_document_id,
+            accounting_document_number: log.accounting_document_number,
+            extra: log.extra,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BillingCycleResponse {
+    /// List of billing cycles
+    pub invoices: Vec<BillingCycle>,
+    /// Total count of billing cycles
+    pub total_count: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BillingCycle {
+    /// Accounting document's identifier
+    pub invoice_id: u64,
+    /// Accounting document's type
+    #[serde(rename = "type")]
+    pub invoice_type: Option<String>,
+    /// Accounting document's creation date
+    pub date_created: String,
+    /// Start of the billing cycle
+    pub start_time: Option<String>,
+    /// End of the billing cycle
+    pub end_time: Option<String>,
+    /// Billing cycle's currency (iso format)
+    pub currency_iso_code: String,
+    /// Total amount charged to the shop
+    pub total_charged_amount: Option<f64>,
+    /// Total of the other credits
+    pub total_other_credits: Option<f64>,
+    /// Summary of the billing cycle
+    pub summary: Option<BillingCycleSummary>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BillingCycleSummary {
+    /// Amount transferred to the shop
+    pub amount_transferred: Option<f64>,
+    /// Total of the commissions, including taxes
+    pub total_commissions_incl_tax: Option<f64>,
+    /// Total of the payable orders, including taxes
+    pub total_payable_orders_incl_tax: Option<f64>,
+    /// Total of the refunded orders, including taxes
+    pub total_refund_orders_incl_tax: Option<f64>,
+    /// Fields not known by the model
+    #[serde(flatten)]
+    pub extra: UnknownFields,
+}
+
+/// A billing cycle, flattened for export.
+#[derive(Debug, Serialize)]
+pub struct BillingCycleRecord {
+    pub invoice_id: u64,
+    pub invoice_type: Option<String>,
+    pub date_created: String,
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    pub currency_iso_code: String,
+    pub total_charged_amount: Option<f64>,
+    pub total_other_credits: Option<f64>,
+    pub amount_transferred: Option<f64>,
+    pub total_commissions_incl_tax: Option<f64>,
+    pub total_payable_orders_incl_tax: Option<f64>,
+    pub total_refund_orders_incl_tax: Option<f64>,
+    /// Fields of the billing cycle and its summary not known by the model, as JSON
+    #[serde(serialize_with = "serialize_as_json")]
+    pub extra: UnknownFields,
+}
+
+impl From<BillingCycle> for BillingCycleRecord {
+    fn from(cycle: BillingCycle) -> Self {
+        let summary = cycle.summary.unwrap_or_default();
+        let mut extra = cycle.extra;
+        if !summary.extra.is_empty() {
+            extra.insert("summary".to_string(), serde_json::to_value(&*summary.extra).unwrap_or_default());
+        }
+        Self {
+            invoice_id: cycle.invoice_id,
+            invoice_type: cycle.invoice_type,
+            date_created: cycle.date_created,
+            start_time: cycle.start_time,
+            end_time: cycle.end_time,
+            currency_iso_code: cycle.currency_iso_code,
+            total_charged_amount: cycle.total_charged_amount,
+            total_other_credits: cycle.total_other_credits,
+            amount_transferred: summary.amount_transferred,
+            total_commissions_incl_tax: summary.total_commissions_incl_tax,
+            total_payable_orders_incl_tax: summary.total_payable_orders_incl_tax,
+            total_refund_orders_incl_tax: summary.total_refund_orders_incl_tax,
+            extra,
+        }
+    }
+}
+
+strip_unknown_fields! {
+    TransactionLog { entities },
+    TransactionEntities { order, order_line },
+    TransactionEntity {},
+    BillingCycle { summary },
+    BillingCycleSummary {},
+}
+
+/// Where the previous `pull-transactions` run stopped, used to resume incrementally.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct TransactionState {
+    /// Creation date of the latest exported transaction
+    pub last_transaction_date: Option<String>,
+    /// Transactions created at `last_transaction_date`, which the next run gets again
+    #[serde(default)]
+    pub last_transaction_ids: Vec<String>,
+    /// Creation date of the latest exported billing cycle
+    pub last_billing_cycle_date: Option<String>,
+    /// Billing cycles created at `last_billing_cycle_date`, which the next run gets again
+    #[serde(default)]
+    pub last_billing_cycle_ids: Vec<u64>,
+}
+
+impl TransactionState {
+    /// Whether the transaction was exported by the runs the state was saved by
+    pub fn transaction_exported(&self, transaction: &TransactionRecord) -> bool {
+        exported(&self.last_transaction_date, &self.last_transaction_ids, &transaction.date_created, &transaction.id)
+    }
+
+    /// Whether the billing cycle was exported by the runs the state was saved by
+    pub fn billing_cycle_exported(&self, cycle: &BillingCycleRecord) -> bool {
+        exported(&self.last_billing_cycle_date, &self.last_billing_cycle_ids, &cycle.date_created, &cycle.invoice_id)
+    }
+
+    /// Moves the state past the exported transactions, never back
+    pub fn advance_transactions(&mut self, transactions: &[TransactionRecord]) {
+        let exported = transactions.iter().map(|t| (t.date_created.as_str(), t.id.clone()));
+        advance(&mut self.last_transaction_date, &mut self.last_transaction_ids, exported);
+    }
+
+    /// Moves the state past the exported billing cycles, never back
+    pub fn advance_billing_cycles(&mut self, cycles: &[BillingCycleRecord]) {
+        let exported = cycles.iter().map(|c| (c.date_created.as_str(), c.invoice_id));
+        advance(&mut self.last_billing_cycle_date, &mut self.last_billing_cycle_ids, exported);
+    }
+}
+
+fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(date).ok()
+}
+
+fn exported<I: PartialEq>(last_date: &Option<String>, last_ids: &[I], date: &str, id: &I) -> bool {
+    match (last_date.as_deref().and_then(parse_date), parse_date(date)) {
+        (Some(last), Some(date)) => date < last || (date == last && last_ids.contains(id)),
+        // exporting a record twice beats losing it
+        _ => false,
+    }
+}
+
+fn advance<'a, I: PartialEq>(last_date: &mut Option<String>, last_ids: &mut Vec<I>, exported: impl Iterator<Item = (&'a str, I)>) {
+    for (date, id) in exported {
+        let created = match parse_date(date) {
+            Some(created) => created,
+            None => continue,
+        };
+        match last_date.as_deref().and_then(parse_date) {
+            Some(last) if created < last => (),
+            Some(last) if created == last => {
+                if !last_ids.contains(&id) {
+                    last_ids.push(id);
+                }
+            },
+            _ => {
+                *last_date = Some(date.to_string());
+                *last_ids = vec![id];
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::unknown::StripUnknownFields;
+
+    fn transaction(id: &str, date_created: &str) -> TransactionRecord {
+        TransactionRecord {
+            id: id.to_string(),
+            transaction_type: "ORDER_AMOUNT".to_string(),
+            amount: 10.0,
+            currency_iso_code: "EUR".to_string(),
+            date_created: date_created.to_string(),
+            last_updated: None,
+            payment_state: None,
+            order_id: None,
+            order_line_id: None,
+            accounting_document_id: None,
+            accounting_document_number: None,
+            extra: UnknownFields::default(),
+        }
+    }
+
+    #[test]
+    fn records_sharing_the_last_date_are_skipped_by_id() {
+        let mut state = TransactionState::default();
+        state.advance_transactions(&[
+            transaction("T1", "2022-12-01T10:00:00Z"),
+            transaction("T2", "2022-12-01T11:00:00+01:00"),
+            transaction("T0", "2022-11-30T10:00:00Z"),
+        ]);
+        assert_eq!(state.last_transaction_date.as_deref(), Some("2022-12-01T10:00:00Z"));
+        assert_eq!(state.last_transaction_ids, vec!["T1", "T2"]);
+
+        assert!(state.transaction_exported(&transaction("T1", "2022-12-01T10:00:00.000Z")));
+        assert!(!state.transaction_exported(&transaction("T3", "2022-12-01T10:00:00Z")));
+        assert!(!state.transaction_exported(&transaction("T4", "2022-12-01T10:00:01Z")));
+    }
+
+    #[test]
+    fn unknown_fields_are_exported_as_a_json_column() {
+        let log: TransactionLog = serde_json::from_value(serde_json::json!({
+            "id": "T1",
+            "type": "ORDER_AMOUNT",
+            "amount": 65.0,
+            "currency_iso_code": "EUR",
+            "date_created": "2022-12-01T10:00:00Z",
+            "entities": { "order": { "id": "1001-A" } },
+            "operator_batch": "B-7",
+        })).unwrap();
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.serialize(TransactionRecord::from(log)).unwrap();
+        writer.serialize(transaction("T2", "2022-12-01T10:00:00Z")).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert!(lines[0].ends_with(",extra"), "{}", csv);
+        assert!(lines[1].ends_with(r#","{""operator_batch"":""B-7""}""#), "{}", csv);
+        assert!(lines[2].ends_with(','), "{}", csv);
+
+        let mut log: TransactionLog = serde_json::from_value(serde_json::json!({
+            "id": "T1", "type": "ORDER_AMOUNT", "amount": 65.0, "currency_iso_code": "EUR",
+            "date_created": "2022-12-01T10:00:00Z", "operator_batch": "B-7",
+        })).unwrap();
+        log.strip_unknown_fields();
+        assert!(log.extra.is_empty());
+    }
+
+    #[test]
+    fn older_exports_do_not_move_the_state_back() {
+        let mut state = TransactionState::default();
+        state.advance_transactions(&[transaction("T5", "2022-12-05T10:00:00Z")]);
+        state.advance_transactions(&[transaction("T1", "2022-12-01T10:00:00Z")]);
+        state.advance_transactions(&[]);
+        assert_eq!(state.last_transaction_date.as_deref(), Some("2022-12-05T10:00:00Z"));
+        assert_eq!(state.last_transaction_ids, vec!["T5"]);
+    }
+}
@@ -0,0 +1,134 @@
+//! Fields sent by the API that the models don't know (yet).
+//!
+//! Each model flattens them into an `extra` map so operator-specific data reaches the exports.
+
+use std::{collections::BTreeMap, marker::PhantomData, ops::{Deref, DerefMut}};
+
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+
+/// Unknown fields of a model, by name
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct UnknownFields(BTreeMap<String, Value>);
+
+impl Deref for UnknownFields {
+    type Target = BTreeMap<String, Value>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for UnknownFields {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+/// quick-xml builds a new serializer type for every nesting level, so serializing a recursive
+/// `Value` never finishes compiling. Values are walked down to a fixed depth instead, deeper
+/// arrays and objects are written as JSON text.
+impl Serialize for UnknownFields {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, value)| (name, Nested::<MaxDepth>::new(value))))
+    }
+}
+
+type MaxDepth = Deeper<Deeper<Deeper<Deeper<Leaf>>>>;
+
+struct Leaf;
+struct Deeper<D>(PhantomData<D>);
+
+struct Nested<'a, D>(&'a Value, PhantomData<D>);
+
+impl<'a, D> Nested<'a, D> {
+    fn new(value: &'a Value) -> Self {
+        Self(value, PhantomData)
+    }
+}
+
+impl Serialize for Nested<'_, Leaf> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Array(_) | Value::Object(_) => serializer.serialize_str(&self.0.to_string()),
+            scalar => serialize_scalar(scalar, serializer),
+        }
+    }
+}
+
+impl<D> Serialize for Nested<'_, Deeper<D>>
+where
+    for<'a> Nested<'a, D>: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Array(items) => serializer.collect_seq(items.iter().map(Nested::<D>::new)),
+            Value::Object(map) => serializer.collect_map(map.iter().map(|(name, value)| (name, Nested::<D>::new(value)))),
+            scalar => serialize_scalar(scalar, serializer),
+        }
+    }
+}
+
+fn serialize_scalar<S: Serializer>(value: &Value, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Value::Bool(value) => serializer.serialize_bool(*value),
+        Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(value), _) => serializer.serialize_u64(value),
+            (_, Some(value)) => serializer.serialize_i64(value),
+            _ => serializer.serialize_f64(number.as_f64().unwrap_or_default()),
+        },
+        Value::String(value) => serializer.serialize_str(value),
+        _ => serializer.serialize_unit(),
+    }
+}
+
+/// Writes unknown fields as a single JSON text column, for flat exports like CSV
+pub fn serialize_as_json<S: Serializer>(fields: &UnknownFields, serializer: S) -> Result<S::Ok, S::Error> {
+    if fields.is_empty() {
+        return serializer.serialize_str("");
+    }
+    let json = serde_json::to_string(&fields.0).map_err(serde::ser::Error::custom)?;
+    serializer.serialize_str(&json)
+}
+
+pub trait StripUnknownFields {
+    /// Drops the unknown fields of the model and of every nested model
+    fn strip_unknown_fields(&mut self);
+}
+
+impl<T: StripUnknownFields> StripUnknownFields for Option<T> {
+    fn strip_unknown_fields(&mut self) {
+        if let Some(model) = self {
+            model.strip_unknown_fields();
+        }
+    }
+}
+
+impl<T: StripUnknownFields> StripUnknownFields for Vec<T> {
+    fn strip_unknown_fields(&mut self) {
+        self.iter_mut().for_each(StripUnknownFields::strip_unknown_fields);
+    }
+}
+
+/// Implements [`StripUnknownFields`] for models with an `extra` map, recursing into the listed fields
+macro_rules! strip_unknown_fields {
+    ($($model:ty { $($field:ident),* $(,)? }),* $(,)?) => {
+        $(impl $crate::models::unknown::StripUnknownFields for $model {
+            fn strip_unknown_fields(&mut self) {
+                self.extra.clear();
+                $($crate::models::unknown::StripUnknownFields::strip_unknown_fields(&mut self.$field);)*
+            }
+        })*
+    };
+}
+
+pub(crate) use strip_unknown_fields;
+
+/// Serializes a model to XML under the `root` tag.
+///
+/// The flattened `extra` map makes serde write models as maps, which have no name to deduce the root tag from.
+pub fn to_xml<T: Serialize>(root: &str, model: &T) -> Result<String, quick_xml::DeError> {
+    let serializer = quick_xml::se::Serializer::with_root(String::new(), Some(root))?;
+    model.serialize(serializer)
+}
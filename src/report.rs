@@ -0,0 +1,168 @@
+//! Machine-readable summary of a run, so monitoring doesn't have to scrape the logs.
+//!
+//! Commands count what they did and record the items that failed on the run's [`RunReport`],
+//! which is written as JSON once the command is done.
+
+use std::{collections::BTreeMap, io::Write, sync::Mutex};
+
+use chrono::{DateTime, Local};
+use miette::Diagnostic;
+use serde::Serialize;
+
+use crate::prelude::*;
+
+/// What a command counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Counter {
+    /// Orders or returns fetched from the marketplace
+    Fetched,
+    /// Orders filtered out locally
+    Skipped,
+    /// Orders left out because a date of theirs could not be read
+    Unreadable,
+    Accepted,
+    Refused,
+    HeldForReview,
+    /// Orders written to the order path
+    Written,
+    /// Orders whose tracking info was pushed
+    Pushed,
+    /// Lines of an offer or product import the marketplace took
+    Imported,
+    /// Lines of an offer or product import the marketplace rejected
+    Rejected,
+    /// Order documents downloaded
+    Downloaded,
+    /// Incidents, returns, transactions or reconciled orders written to a file
+    Exported,
+    /// Orders the reconciliation found a discrepancy on
+    Mismatched,
+    /// Incidents, return lines or orders whose additional fields were changed on the marketplace
+    Updated,
+    /// Items that could not be processed
+    Failed,
+}
+
+/// An error of the run, with the code of the [`WunderError`] it comes from
+#[derive(Debug, Serialize)]
+pub struct ReportedError {
+    /// The order (or other item) the error is about, `None` for the error that ended the run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item: Option<String>,
+    pub code: Option<String>,
+    pub message: String,
+}
+
+impl ReportedError {
+    fn new(item: Option<String>, error: &WunderError) -> Self {
+        Self {
+            item,
+            code: error.code().map(|code| code.to_string()),
+            message: match error {
+                // the label is what a diagnostic shows next to the offending source
+                WunderError::InvalidTrackingFile(error) => format!("{}: {}", error, error.label),
+                error => error.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tally {
+    counts: BTreeMap<Counter, usize>,
+    errors: Vec<ReportedError>,
+}
+
+/// Counts and errors of the current run, shared by everything the command does
+#[derive(Debug)]
+pub struct RunReport {
+    started_at: DateTime<Local>,
+    tally: Mutex<Tally>,
+}
+
+impl Default for RunReport {
+    fn default() -> Self {
+        Self {
+            started_at: Local::now(),
+            tally: Mutex::default(),
+        }
+    }
+}
+
+/// The JSON document written at the end of a run
+#[derive(Debug, Serialize)]
+struct RunSummary<'a> {
+    command: &'a str,
+    started_at: String,
+    finished_at: String,
+    success: bool,
+    counts: &'a BTreeMap<Counter, usize>,
+    errors: &'a [ReportedError],
+    error: Option<ReportedError>,
+}
+
+impl RunReport {
+    /// Adds `n` to a counter
+    pub fn count(&self, counter: Counter, n: usize) {
+        let mut tally = self.tally.lock().unwrap_or_else(|e| e.into_inner());
+        *tally.counts.entry(counter).or_default() += n;
+    }
+
+    /// Records an item that failed, and counts it
+    pub fn failure(&self, item: &str, error: &WunderError) {
+        let mut tally = self.tally.lock().unwrap_or_else(|e| e.into_inner());
+        *tally.counts.entry(Counter::Failed).or_default() += 1;
+        tally.errors.push(ReportedError::new(Some(item.to_string()), error));
+    }
+
+    /// Writes the report of the `command` that ended with `result`, to stdout when `path` is `-`
+    pub fn write(&self, path: &str, command: &str, result: &Result<()>) -> Result<()> {
+        let tally = self.tally.lock().unwrap_or_else(|e| e.into_inner());
+        let summary = RunSummary {
+            command,
+            started_at: self.started_at.to_rfc3339(),
+            finished_at: Local::now().to_rfc3339(),
+            success: result.is_ok(),
+            counts: &tally.counts,
+            errors: &tally.errors,
+            error: result.as_ref().err().map(|error| ReportedError::new(None, error)),
+        };
+        if path == "-" {
+            let mut stdout = std::io::stdout().lock();
+            serde_json::to_writer_pretty(&mut stdout, &summary)?;
+            writeln!(stdout)?;
+        } else {
+            serde_json::to_writer_pretty(std::fs::File::create(path)?, &summary)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_lists_counts_and_failures_with_codes() {
+        let report = RunReport::default();
+        report.count(Counter::Fetched, 3);
+        report.count(Counter::Written, 2);
+        report.failure("1001-A", &WunderError::OrderNotWaitingAcceptance("1001-A".to_string()));
+
+        let path = std::env::temp_dir().join(format!("wunder-report-{}.json", std::process::id()));
+        let result = Err(WunderError::OrdersNotFound("1002-A".to_string()));
+        report.write(path.to_str().unwrap(), "pull-orders", &result).unwrap();
+        let written: serde_json::Value = serde_json::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(written["command"], "pull-orders");
+        assert_eq!(written["success"], false);
+        assert_eq!(written["counts"], serde_json::json!({ "fetched": 3, "written": 2, "failed": 1 }));
+        assert_eq!(written["errors"][0]["item"], "1001-A");
+        assert_eq!(written["errors"][0]["code"], "wunder::error::order_not_waiting_acceptance");
+        assert_eq!(written["error"]["code"], "wunder::error::orders_not_found");
+        assert!(written["error"].get("item").is_none());
+    }
+}
@@ -0,0 +1,237 @@
+//! Runs the CLI against the mock server, the way it runs against the marketplace.
+
+use std::{
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+};
+
+use serde_json::Value;
+
+const WUNDER: &str = env!("CARGO_BIN_EXE_wunder");
+
+/// The mock server, stopped when dropped
+struct MockServer {
+    process: Child,
+    base_url: String,
+}
+
+impl MockServer {
+    /// Starts the mock server on a port picked by the system
+    fn start(workdir: &Path) -> Self {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/orders");
+        let mut process = Command::new(WUNDER)
+            .args(["mock-server", fixtures, "--addr", "127.0.0.1:0"])
+            .current_dir(workdir)
+            .env("RUST_LOG", "info")
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("mock server starts");
+
+        // the bound address is logged once listening
+        let mut lines = BufReader::new(process.stdout.take().unwrap()).lines();
+        let base_url = lines.by_ref()
+            .map(|line| line.expect("mock server output"))
+            .find_map(|line| {
+                let address = line.split("listening on ").nth(1)?;
+                Some(address.chars().take_while(|c| !c.is_whitespace() && *c != '\u{1b}').collect::<String>())
+            })
+            .expect("mock server logs its address");
+        // the server keeps logging, it must not write to a closed pipe
+        std::thread::spawn(move || lines.for_each(drop));
+
+        Self { process, base_url }
+    }
+
+    async fn get(&self, path: &str) -> Value {
+        reqwest::get(format!("{}{}", self.base_url, path)).await.unwrap()
+            .error_for_status().unwrap()
+            .json().await.unwrap()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// An empty folder with a config pointing at the mock server
+fn workdir(name: &str) -> PathBuf {
+    let workdir = std::env::temp_dir().join(format!("wunder-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&workdir);
+    std::fs::create_dir_all(&workdir).unwrap();
+    workdir
+}
+
+fn configure(workdir: &Path, server: &MockServer) {
+    let config = format!(
+        "base_url = \"{}\"\napi_key = \"secret\"\n[pull_order_settings]\norder_state_codes = [\"SHIPPING\"]\norder_path = \"orders\"\n",
+        server.base_url,
+    );
+    std::fs::write(workdir.join("config.toml"), config).unwrap();
+}
+
+fn wunder(workdir: &Path, args: &[&str]) {
+    let output = Command::new(WUNDER)
+        .args(args)
+        .current_dir(workdir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "wunder {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr),
+    );
+}
+
+/// The content of the only file of `folder`
+fn only_file(folder: &Path) -> String {
+    let files = std::fs::read_dir(folder).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    assert_eq!(files.len(), 1, "{:?}", files);
+    std::fs::read_to_string(&files[0]).unwrap()
+}
+
+#[tokio::test]
+async fn orders_are_pulled_shipped_and_invoiced() {
+    let workdir = workdir("mock");
+    let server = MockServer::start(&workdir);
+    configure(&workdir, &server);
+
+    wunder(&workdir, &["pull-orders", "--state", "SHIPPING"]);
+    let pulled = std::fs::read_dir(workdir.join("orders")).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(pulled.len(), 1, "{:?}", pulled);
+    assert!(pulled[0].contains("1002-A"), "{:?}", pulled);
+
+    std::fs::write(
+        workdir.join("tracking.xml"),
+        "<tracking><order_id>1002-A</order_id><carrier_code>DHL</carrier_code><tracking_number>123</tracking_number></tracking>",
+    ).unwrap();
+    wunder(&workdir, &["push-tracking-info", "tracking.xml"]);
+    let orders = server.get("/api/orders?order_ids=1002-A").await;
+    assert_eq!(orders["orders"][0]["order_state"], "SHIPPED");
+    assert_eq!(orders["orders"][0]["shipping_tracking"], "123");
+
+    std::fs::write(workdir.join("1002-A_invoice.pdf"), "%PDF-1.4").unwrap();
+    wunder(&workdir, &["push-invoice", "1002-A_invoice.pdf"]);
+    let documents = server.get("/api/orders/documents?order_ids=1002-A").await;
+    assert_eq!(documents["total_count"], 1);
+    assert_eq!(documents["order_documents"][0]["file_name"], "Invoice-1002-A.pdf");
+    assert_eq!(documents["order_documents"][0]["type"], "CUSTOMER_INVOICE");
+
+    drop(server);
+    let _ = std::fs::remove_dir_all(&workdir);
+}
+
+#[tokio::test]
+async fn tracking_csv_columns_come_in_any_order() {
+    let workdir = workdir("tracking-csv");
+    let server = MockServer::start(&workdir);
+    configure(&workdir, &server);
+
+    std::fs::write(
+        workdir.join("tracking.csv"),
+        "carrier_code,tracking_number,order_id\nDHL,123,1002-A\nNOPE,456,1001-A\n",
+    ).unwrap();
+    let status = Command::new(WUNDER)
+        .args(["push-tracking-info", "tracking.csv"])
+        .current_dir(&workdir)
+        .output()
+        .unwrap()
+        .status;
+    assert!(!status.success());
+
+    let results = only_file(&workdir.join("reports"));
+    let lines = results.lines().collect::<Vec<_>>();
+    assert!(lines[1].starts_with("1002-A,2,PUSHED,"), "{}", results);
+    assert!(lines[2].starts_with("1001-A,3,FAILED,"), "{}", results);
+    let orders = server.get("/api/orders?order_ids=1002-A").await;
+    assert_eq!(orders["orders"][0]["shipping_tracking"], "123");
+
+    drop(server);
+    let _ = std::fs::remove_dir_all(&workdir);
+}
+
+#[tokio::test]
+async fn report_is_written_when_the_input_file_is_missing() {
+    let workdir = workdir("report");
+    let server = MockServer::start(&workdir);
+    configure(&workdir, &server);
+
+    let status = Command::new(WUNDER)
+        .args(["--report", "report.json", "push-offers", "missing.csv"])
+        .current_dir(&workdir)
+        .output()
+        .unwrap()
+        .status;
+    assert!(!status.success());
+
+    let report: Value = serde_json::from_str(&std::fs::read_to_string(workdir.join("report.json")).unwrap()).unwrap();
+    assert_eq!(report["command"], "push-offers");
+    assert_eq!(report["success"], false);
+    assert_eq!(report["error"]["code"], "wunder::error::stock_file_not_found");
+
+    drop(server);
+    let _ = std::fs::remove_dir_all(&workdir);
+}
+
+#[tokio::test]
+async fn manually_accepted_orders_are_written_for_the_erp() {
+    let workdir = workdir("accept");
+    let server = MockServer::start(&workdir);
+    configure(&workdir, &server);
+
+    wunder(&workdir, &["accept-orders", "1001-A"]);
+    let orders = server.get("/api/orders?order_ids=1001-A").await;
+    assert_ne!(orders["orders"][0]["order_state"], "WAITING_ACCEPTANCE");
+    assert!(only_file(&workdir.join("orders")).contains("<order_id>1001-A</order_id>"));
+
+    drop(server);
+    let _ = std::fs::remove_dir_all(&workdir);
+}
+
+#[tokio::test]
+async fn dry_runs_report_tracking_info_as_not_sent() {
+    let workdir = workdir("dry-run");
+    let server = MockServer::start(&workdir);
+    configure(&workdir, &server);
+
+    std::fs::write(workdir.join("tracking.csv"), "order_id,carrier_code,tracking_number\n1002-A,DHL,123\n").unwrap();
+    wunder(&workdir, &["--dry-run", "--report", "report.json", "push-tracking-info", "tracking.csv"]);
+
+    let results = only_file(&workdir.join("dry-run").join("reports"));
+    assert!(results.lines().nth(1).unwrap().starts_with("1002-A,2,NOT_SENT,"), "{}", results);
+    let report: Value = serde_json::from_str(&std::fs::read_to_string(workdir.join("report.json")).unwrap()).unwrap();
+    assert!(report["counts"].get("pushed").is_none(), "{}", report);
+    let orders = server.get("/api/orders?order_ids=1002-A").await;
+    assert_eq!(orders["orders"][0]["order_state"], "SHIPPING");
+
+    drop(server);
+    let _ = std::fs::remove_dir_all(&workdir);
+}
+
+#[test]
+fn report_is_written_when_the_config_is_missing() {
+    let workdir = workdir("no-config");
+
+    let status = Command::new(WUNDER)
+        .args(["--report", "report.json", "pull-orders"])
+        .current_dir(&workdir)
+        .output()
+        .unwrap()
+        .status;
+    assert!(!status.success());
+
+    let report: Value = serde_json::from_str(&std::fs::read_to_string(workdir.join("report.json")).unwrap()).unwrap();
+    assert_eq!(report["command"], "pull-orders");
+    assert_eq!(report["success"], false);
+    assert_eq!(report["error"]["code"], "wunder::error::config");
+
+    let _ = std::fs::remove_dir_all(&workdir);
+}